@@ -0,0 +1,129 @@
+use embassy_executor::Spawner;
+use embassy_time::Timer;
+use log::info;
+use pmp_config::{Library, Track};
+
+use crate::{
+    fs::{decode, FileSystem},
+    input::{InputEvent, Receiver},
+    player::{
+        bookmarks::{Bookmarks, MAX_BOOKMARKS},
+        queue::{Queue, RepeatMode, MAX_QUEUE},
+        PlaybackState, Player, TrackDecoder,
+    },
+    settings::Settings,
+};
+
+pub async fn run<'a, 'b, 'ch>(
+    _spawner: Spawner,
+    fs: &'a FileSystem<'a>,
+    mut player: Player<'a, 'b>,
+    input: Receiver<'ch>,
+) -> ! {
+    info!("Run App");
+    let file = esp_println::dbg!(fs.open_file("library.post")).unwrap();
+    loop {}
+    let lib: Library = decode(fs.open_file("library.post").unwrap()).unwrap();
+    Settings::load(fs).apply(&mut player);
+
+    // loop {}
+    let mut queue: Queue<_, MAX_QUEUE> = Queue::from_items(lib.playlists[0].tracks.iter());
+    queue.set_repeat(RepeatMode::All);
+    let mut bookmarks: Bookmarks<&str, MAX_BOOKMARKS> = Bookmarks::new();
+    queue.next();
+    play_current(fs, &mut player, &mut queue, &mut bookmarks);
+    // Whether the player holds the track the queue advances to next
+    let mut queued = false;
+    loop {
+        // let a = spawner.spawn(test());
+        if queued && !player.has_queued() {
+            // The player has spliced on the queued track
+            queued = false;
+            queue.advance();
+        }
+        // Open the upcoming track ahead of time for gapless playback
+        if !queued && player.state() != PlaybackState::Stopped {
+            if let Some(index) = queue.upcoming() {
+                let track = queue.items()[index];
+                match fs.open_track(track) {
+                    Ok(decoder) => {
+                        player.queue(decoder);
+                        queued = true;
+                    }
+                    // Try the one after it on the next pass
+                    Err(err) => {
+                        log::warn!("Skipping {}: {:?}", track.title, err);
+                        queue.remove(index);
+                    }
+                }
+            }
+        }
+        while let Ok(event) = input.try_receive() {
+            match event {
+                InputEvent::IncrementVolume => player.step_volume(1),
+                InputEvent::DecrementVolume => player.step_volume(-1),
+                InputEvent::Enter => match player.state() {
+                    // Playback only starts again after a stop when asked to
+                    PlaybackState::Stopped => {
+                        play_current(fs, &mut player, &mut queue, &mut bookmarks)
+                    }
+                    _ => player.toggle_pause(),
+                },
+                InputEvent::Back => {
+                    // Carry on from here the next time the track is played
+                    if let (Some(track), Some(at)) = (player.track(), player.heard()) {
+                        bookmarks.save(track.title.as_str(), at);
+                    }
+                    player.stop();
+                }
+                _ => {}
+            }
+        }
+        player.next().await;
+
+        Timer::after_nanos(100).await;
+    }
+}
+
+/// Play the current track of the queue, taking out any that fail to open
+fn play_current<'a, 'b>(
+    fs: &'a FileSystem<'a>,
+    player: &mut Player<'a, 'b>,
+    queue: &mut Queue<&'b Track, MAX_QUEUE>,
+    bookmarks: &mut Bookmarks<&'b str, MAX_BOOKMARKS>,
+) {
+    while let Some(index) = queue.current_index() {
+        match open(fs, queue.items()[index], bookmarks) {
+            Some(decoder) => return player.play(decoder),
+            None => {
+                queue.remove(index);
+            }
+        }
+    }
+}
+
+/// Open `track`, resuming from its bookmark if it has one
+///
+/// Tracks that cannot be played are logged and `None` is returned.
+fn open<'a, 'b>(
+    fs: &'a FileSystem<'a>,
+    track: &'b Track,
+    bookmarks: &mut Bookmarks<&'b str, MAX_BOOKMARKS>,
+) -> Option<TrackDecoder<'a, 'b>> {
+    let mut decoder = match fs.open_track(track) {
+        Ok(decoder) => decoder,
+        Err(err) => {
+            log::warn!("Skipping {}: {:?}", track.title, err);
+            return None;
+        }
+    };
+    if let Some(at) = bookmarks.remove(&track.title.as_str()) {
+        if let Err(err) = decoder.seek(at) {
+            log::warn!("Failed to resume {} at {}: {:?}", track.title, at, err);
+        }
+    }
+    Some(decoder)
+}
+
+#[embassy_executor::task]
+async fn test() {}
@@ -19,6 +19,7 @@ use esp_hal::clock::CpuClock;
 use esp_hal::delay::Delay;
 use esp_hal::dma::{DmaRxBuf, DmaTxBuf};
 use esp_hal::gpio::{Input, InputConfig, Level, Output, OutputConfig};
+use esp_hal::interrupt::software::SoftwareInterruptControl;
 use esp_hal::spi::slave::dma::SpiDma;
 use esp_hal::spi::slave::Spi;
 use esp_hal::spi::DataMode;
@@ -29,7 +30,10 @@ use esp_println::{dbg, println};
 use log::{info, warn};
 use portable_music_player::fs::FileSystem;
 use portable_music_player::input::spawn_input_task;
-use portable_music_player::player::{Player, Sink};
+use portable_music_player::player::output::{
+    spawn_output_task, start_output_executor, DEFAULT_WATERMARK,
+};
+use portable_music_player::player::{I2sParts, Player};
 
 // extern crate alloc;
 
@@ -42,6 +46,9 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
 static INPUT_CHANNEL: portable_music_player::input::Channel =
     portable_music_player::input::Channel::new();
 
+static OUTPUT: portable_music_player::player::output::Output =
+    portable_music_player::player::output::Output::new();
+
 // This creates a default app-descriptor required by the esp-idf bootloader.
 // For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
 esp_bootloader_esp_idf::esp_app_desc!();
@@ -92,6 +99,7 @@ async fn main(spawner: Spawner) -> ! {
     // Test
     println!("[LOOK_HERE] {:?}", sd.num_bytes());
 
+    // let software_interrupts = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
     // portable_music_player::app::run(
     //     spawner,
     //     &FileSystem::new(
@@ -103,19 +111,21 @@ async fn main(spawner: Spawner) -> ! {
     //         peripherals.GPIO2,  // DAT0 / MISO
     //     )
     //     .unwrap(),
-    //     Player::new(
+    //     Player::new(spawn_output_task(
+    //         start_output_executor(software_interrupts.software_interrupt2),
+    //         &OUTPUT,
     //         // ES7243 DAC
-    //         Sink::new(
-    //             peripherals.I2S1,
-    //             peripherals.DMA_I2S1,
-    //             peripherals.GPIO0,  // MCLK
-    //             peripherals.GPIO32, // BLCK
-    //             peripherals.GPIO33, // WS
-    //             tx_descriptors,
-    //             tx_buffer,
-    //         )
-    //         .unwrap(),
-    //     ),
+    //         I2sParts {
+    //             i2s: peripherals.I2S1,
+    //             dma: peripherals.DMA_I2S1,
+    //             mclk: peripherals.GPIO0.into(),  // MCLK
+    //             bclk: peripherals.GPIO32.into(), // BLCK
+    //             ws: peripherals.GPIO33.into(),   // WS
+    //             descriptors: tx_descriptors,
+    //             buffer: tx_buffer,
+    //         },
+    //         DEFAULT_WATERMARK,
+    //     )),
     // spawn_input_task(
     //     &spawner,
     //     &INPUT_CHANNEL,
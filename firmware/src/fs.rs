@@ -17,9 +17,11 @@ use esp_hal::{
 use esp_println::{dbg, println};
 use pmp_config::Track;
 use postcard::accumulator::{CobsAccumulator, FeedResult};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::player::TrackDecoder;
+use crate::{codec, player::TrackDecoder};
+
+pub use pmp_core::fs::{ReadAhead, ReadRate, ReadStats, SliceSource, Source, SD_BLOCK};
 
 const MAX_DIRS: usize = 4;
 const MAX_FILES: usize = 4;
@@ -37,7 +39,7 @@ pub type Directory<'a> =
     embedded_sdmmc::Directory<'a, SdCard<'a>, DummyTimesource, MAX_DIRS, MAX_FILES, MAX_VOLUMES>;
 pub type VolumeManager<'a> =
     embedded_sdmmc::VolumeManager<SdCard<'a>, DummyTimesource, MAX_DIRS, MAX_FILES, MAX_VOLUMES>;
-type Error = embedded_sdmmc::Error<embedded_sdmmc::SdCardError>;
+pub type Error = embedded_sdmmc::Error<embedded_sdmmc::SdCardError>;
 
 /// A dummy timesource, which is mostly important for creating files.
 #[derive(Default)]
@@ -58,6 +60,29 @@ impl TimeSource for DummyTimesource {
     }
 }
 
+/// [`File`] read by the decoders as a [`Source`]
+pub struct TrackFile<'a>(pub File<'a>);
+
+impl Source for TrackFile<'_> {
+    type Error = Error;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.0.read(buf)
+    }
+
+    fn seek_from_start(&mut self, offset: u32) -> Result<(), Error> {
+        self.0.seek_from_start(offset)
+    }
+
+    fn offset(&self) -> u32 {
+        self.0.offset()
+    }
+
+    fn length(&self) -> u32 {
+        self.0.length()
+    }
+}
+
 /// Decoding Errors
 #[derive(Debug)]
 pub enum DecodeError {
@@ -89,6 +114,21 @@ pub fn decode<T: for<'de> Deserialize<'de>>(file: File) -> Result<T, DecodeError
     }
 }
 
+/// Encoding Errors
+#[derive(Debug)]
+pub enum EncodeError {
+    Write,
+    SerError,
+}
+
+/// Encode `value` into a file, in the format read back by [`decode`]
+pub fn encode<T: Serialize>(file: File, value: &T) -> Result<(), EncodeError> {
+    let mut buf = [0u8; 256];
+    let data = postcard::to_slice_cobs(value, &mut buf).map_err(|_| EncodeError::SerError)?;
+    file.write(data).map_err(|_| EncodeError::Write)?;
+    file.close().map_err(|_| EncodeError::Write)
+}
+
 /// File System wrapper for embedded_sdmmc
 pub struct FileSystem<'a>(VolumeManager<'a>);
 
@@ -149,7 +189,21 @@ impl<'a> FileSystem<'a> {
             .to_file(&self.0))
     }
 
-    pub fn open_track<'b>(&'a self, track: &'b Track) -> Result<TrackDecoder<'a, 'b>, Error> {
+    /// Create the file `name`, or empty it if it exists
+    pub fn create_file(&'a self, name: impl ToShortFileName) -> Result<File<'a>, Error> {
+        Ok(self
+            .0
+            .open_volume(VolumeIdx(0))?
+            .open_root_dir()?
+            .open_file_in_dir(name, embedded_sdmmc::Mode::ReadWriteCreateOrTruncate)?
+            .to_raw_file()
+            .to_file(&self.0))
+    }
+
+    pub fn open_track<'b>(
+        &'a self,
+        track: &'b Track,
+    ) -> Result<TrackDecoder<'a, 'b>, codec::Error<Error>> {
         let file = self.open_file(track.title.as_str())?;
         TrackDecoder::new(track, file)
     }
@@ -10,5 +10,8 @@ pub mod app;
 pub mod fs;
 pub mod input;
 pub mod player;
+pub mod settings;
 mod ui;
 mod visualizer;
+
+pub use pmp_core::{codec, shared};
@@ -0,0 +1,911 @@
+use embassy_time::{Duration, Instant, Timer};
+use esp_hal::{
+    dma::{DmaDescriptor, DmaError},
+    gpio::AnyPin,
+    i2s::master::{asynch::I2sWriteDmaTransferAsync, DataFormat, I2s, Standard},
+    peripherals::{DMA_I2S1, I2S1},
+    time::Rate,
+};
+use pmp_config::Track;
+
+use crate::{
+    codec::{self, AudioDecoder, Codec, PcmInfo, MAX_SAMPLES},
+    fs::{self, File, ReadAhead, ReadRate, ReadStats, TrackFile, SD_BLOCK},
+    shared::SharedBuffer,
+    visualizer::Visualizer,
+};
+
+use self::{
+    channels::{ChannelMixer, ChannelMode},
+    circular::{self, Underruns},
+    crossfeed::{Crossfeed, CrossfeedLevel},
+    dsp::{Chain, Effect, Processor, MAX_EFFECTS},
+    eq::{Equalizer, Preset},
+    gain::{Normalizer, ReplayGainMode},
+    limiter::Limiter,
+    output::OutputHandle,
+    pcm::{Quantizer, Tpdf, BYTES_PER_SAMPLE},
+    position::PlaybackPosition,
+    resampler::{Resampler, MAX_CHANNELS},
+    stretch::{TimeStretch, HOP},
+    tags::Tags,
+    volume::{Ramp, Volume},
+};
+
+pub use pmp_core::player::{
+    bookmarks, channels, circular, crossfeed, dsp, eq, gain, limiter, pcm, position, queue,
+    resampler, ring, rng, stretch, tags, volume,
+};
+
+pub mod output;
+
+/// Rate the I2S transfer runs at, tracks at other rates are resampled
+const OUTPUT_SAMPLE_RATE: u32 = 44100;
+/// Time pausing, resuming and stopping fade over
+const FADE_MS: u32 = 30;
+/// Time the player waits between checks while paused or stopped
+const IDLE_MS: u64 = 10;
+/// Bytes of a track read from the card at a time
+const READ_AHEAD: usize = 8 * SD_BLOCK;
+/// Read-ahead buffer of the open tracks, only one of which is decoded at a time
+static READ_AHEAD_BUFFER: SharedBuffer<[u8; READ_AHEAD]> = SharedBuffer::new([0; READ_AHEAD]);
+/// Time between logs of the read rate of a track
+const READ_STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Peripherals and buffers of the I2S output
+pub struct I2sParts<'a> {
+    pub i2s: I2S1<'a>,
+    pub dma: DMA_I2S1<'a>,
+    pub mclk: AnyPin<'a>,
+    pub bclk: AnyPin<'a>,
+    pub ws: AnyPin<'a>,
+    pub descriptors: &'static mut [DmaDescriptor],
+    pub buffer: &'static mut [u8],
+}
+
+type Transfer<'a> = I2sWriteDmaTransferAsync<'a, &'static mut [u8]>;
+
+pub struct Sink<'a> {
+    volume: Volume,
+    ramp: Ramp,
+    /// Fade of pause, resume and stop, on top of the volume
+    fade: Ramp,
+    limiter: Limiter,
+    sample_rate: u32,
+    quantizer: Quantizer,
+    underruns: Underruns,
+    /// Peripherals and buffers of the transfer, kept to start it again after an underrun
+    i2s: I2S1<'a>,
+    dma: DMA_I2S1<'a>,
+    _mclk: AnyPin<'a>,
+    bclk: AnyPin<'a>,
+    ws: AnyPin<'a>,
+    descriptors: *mut [DmaDescriptor],
+    buffer: *mut [u8],
+    /// `None` after an underrun until the next write starts a new transfer
+    driver: Option<Transfer<'a>>,
+}
+
+impl<'a> Sink<'a> {
+    pub fn new(parts: I2sParts<'a>) -> Result<Self, esp_hal::i2s::master::Error> {
+        let mut sink = Self {
+            volume: Volume::default(),
+            ramp: Ramp::new(Volume::default().gain(), OUTPUT_SAMPLE_RATE),
+            fade: Ramp::with_ms(0., OUTPUT_SAMPLE_RATE, FADE_MS),
+            limiter: Limiter::new(OUTPUT_SAMPLE_RATE),
+            sample_rate: OUTPUT_SAMPLE_RATE,
+            quantizer: Quantizer::new(),
+            underruns: Underruns::default(),
+            i2s: parts.i2s,
+            dma: parts.dma,
+            _mclk: parts.mclk,
+            bclk: parts.bclk,
+            ws: parts.ws,
+            descriptors: parts.descriptors,
+            buffer: parts.buffer,
+            driver: None,
+        };
+        sink.driver()?;
+        Ok(sink)
+    }
+
+    /// The running transfer, started on a silent buffer if there is none
+    fn driver(&mut self) -> Result<&mut Transfer<'a>, esp_hal::i2s::master::Error> {
+        if self.driver.is_none() {
+            // Safety: there is no other transfer, so the peripherals and buffers are unused
+            let driver = unsafe {
+                let buffer = &mut *self.buffer;
+                buffer.fill(0);
+                I2s::new(
+                    self.i2s.clone_unchecked(),
+                    Standard::Philips,
+                    DataFormat::Data16Channel16,
+                    Rate::from_hz(OUTPUT_SAMPLE_RATE),
+                    self.dma.clone_unchecked(),
+                )
+                .into_async()
+                // .with_mclk(mclk)
+                .i2s_tx
+                .with_bclk(self.bclk.clone_unchecked())
+                .with_ws(self.ws.clone_unchecked())
+                .build(&mut *self.descriptors)
+                .write_dma_circular_async(buffer)?
+            };
+            self.driver = Some(driver);
+        }
+        Ok(self.driver.as_mut().expect("Started above"))
+    }
+
+    /// Enable TPDF dither when quantising to 16-bit
+    pub fn with_dither(mut self) -> Self {
+        self.quantizer.set_dither(Some(Tpdf::default()));
+        self
+    }
+
+    /// Output sample rate, fixed for the lifetime of the circular transfer
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn volume(&self) -> Volume {
+        self.volume
+    }
+
+    pub fn diagnostics(&self) -> Diagnostics {
+        Diagnostics {
+            limited_samples: self.limiter.limited_samples(),
+            underruns: self.underruns.count(),
+        }
+    }
+
+    /// Ramp to the gain of `volume`
+    pub fn set_volume(&mut self, volume: Volume) {
+        self.volume = volume;
+        self.ramp.set_target(volume.gain());
+    }
+
+    /// Fade in to full level, or out to silence
+    pub fn fade(&mut self, fade_in: bool) {
+        self.fade.set_target(if fade_in { 1. } else { 0. });
+    }
+
+    /// True once a fade out has finished, only silence is written from then on
+    pub fn is_faded_out(&self) -> bool {
+        self.fade.target() == 0. && self.fade.remaining() == 0
+    }
+
+    /// Write interleaved stereo frames, `pcm_buf` is scaled in place
+    async fn write_frame(
+        &mut self,
+        pcm_buf: &mut [f32],
+    ) -> Result<(), esp_hal::i2s::master::Error> {
+        let bytes = &mut [0u8; MAX_SAMPLES * BYTES_PER_SAMPLE];
+
+        // Frames within a volume change or fade get a gain each, the rest share the final one
+        let remaining = self.ramp.remaining().max(self.fade.remaining());
+        let ramped = (remaining * 2).min(pcm_buf.len());
+        let (ramping, steady) = pcm_buf.split_at_mut(ramped);
+        for frame in ramping.chunks_mut(2) {
+            let gain = self.ramp.advance() * self.fade.advance();
+            frame.iter_mut().for_each(|sample| *sample *= gain);
+        }
+        let gain = self.ramp.gain() * self.fade.gain();
+        steady.iter_mut().for_each(|sample| *sample *= gain);
+
+        // Boosts from ReplayGain and the effects are limited as the very last step
+        self.limiter.process(pcm_buf, 2);
+
+        let n = self.quantizer.write(pcm_buf, 1., bytes);
+
+        self.write(&bytes[..n]).await
+    }
+
+    /// Write a buffer of silence, keeping the circular transfer from replaying stale audio
+    async fn write_silence(&mut self) -> Result<(), esp_hal::i2s::master::Error> {
+        // Nothing is audible under silence, so a fade out in progress is done
+        if self.fade.target() == 0. {
+            self.fade = Ramp::with_ms(0., self.sample_rate, FADE_MS);
+        }
+        self.write(&[0u8; MAX_SAMPLES * BYTES_PER_SAMPLE]).await
+    }
+
+    async fn write(&mut self, mut bytes: &[u8]) -> Result<(), esp_hal::i2s::master::Error> {
+        while !bytes.is_empty() {
+            match self.driver()?.push(bytes).await {
+                Ok(n) => {
+                    bytes = &bytes[n..];
+                    self.underruns.written();
+                }
+                // The transfer played everything written and went round the buffer again
+                Err(esp_hal::i2s::master::Error::DmaError(DmaError::Late)) => self.recover(),
+                Err(err) => return Err(err),
+            }
+        }
+        match self.clear_ahead().await {
+            Err(esp_hal::i2s::master::Error::DmaError(DmaError::Late)) => {
+                self.recover();
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Drop a transfer that ran late, the next write starts a new one from silence
+    ///
+    /// A late transfer never reports free space again, so pushing to it would retry forever.
+    fn recover(&mut self) {
+        self.underruns.late();
+        self.driver = None;
+    }
+
+    /// Zero the free part of the circular buffer, which the transfer plays next if the following
+    /// write comes too late, so an underrun is silent instead of a loop of stale audio
+    async fn clear_ahead(&mut self) -> Result<(), esp_hal::i2s::master::Error> {
+        let (buffer, len) = (self.buffer as *mut u8, self.buffer.len());
+        let driver = self.driver()?;
+        let available = driver.available().await?;
+        // Nothing is committed, the zeros are overwritten by the next push
+        driver
+            .push_with(|free| {
+                let offset = free.as_ptr() as usize - buffer as usize;
+                let [ahead, wrapped] = circular::free_ranges(len, offset, available);
+                // The slice given stops at the end of the buffer, the free part may carry on
+                // from its start
+                free[..ahead.len().min(free.len())].fill(0);
+                // Safety: the range is in the buffer, in the part already played
+                unsafe {
+                    core::slice::from_raw_parts_mut(buffer.add(wrapped.start), wrapped.len())
+                }
+                .fill(0);
+                0
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+/// What the [`Player`] is doing
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackState {
+    /// No track open, silence is output
+    #[default]
+    Stopped,
+    Playing,
+    /// The track is kept open at its position, silence is output
+    Paused,
+}
+
+/// Counters of problems in the output for display
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Diagnostics {
+    /// Samples the limiter had to turn down to stay below full scale
+    pub limited_samples: u64,
+    /// Times the output ran out of audio and played silence
+    pub underruns: u64,
+}
+
+/// Playback progress of the current track
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub elapsed: PlaybackPosition,
+    /// `None` if the length of the track could not be determined, in which case the UI only
+    /// shows the elapsed time
+    pub duration: Option<PlaybackPosition>,
+}
+
+impl Progress {
+    pub fn remaining(&self) -> Option<PlaybackPosition> {
+        self.duration
+            .map(|duration| duration.saturating_sub(self.elapsed))
+    }
+
+    /// Fraction of the track played in [0, 1]
+    pub fn fraction(&self) -> Option<f32> {
+        self.duration
+            .filter(|duration| duration.samples() > 0)
+            .map(|duration| {
+                let elapsed = self.elapsed.at_rate(duration.sample_rate());
+                (elapsed.samples() as f32 / duration.samples() as f32).min(1.)
+            })
+    }
+}
+
+/// Section of a track played over and over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopSection {
+    pub start: PlaybackPosition,
+    pub end: PlaybackPosition,
+}
+
+pub struct TrackDecoder<'a, 'b> {
+    codec: Codec,
+    visualizer: Visualizer,
+    track: &'b Track,
+    file: ReadAhead<TrackFile<'a>, READ_AHEAD>,
+    /// Read stats as of the last log, and when that was
+    logged_stats: (ReadStats, Instant),
+    read_rate: Option<ReadRate>,
+    tags: Tags,
+    position: PlaybackPosition,
+    duration: Option<PlaybackPosition>,
+    /// A point of an A-B loop waiting for its B point
+    loop_start: Option<PlaybackPosition>,
+    loop_section: Option<LoopSection>,
+}
+
+impl<'a, 'b> TrackDecoder<'a, 'b> {
+    pub fn new(track: &'b Track, file: File<'a>) -> Result<Self, codec::Error<fs::Error>> {
+        let mut file = ReadAhead::new(TrackFile(file), &READ_AHEAD_BUFFER);
+        let (tags, audio) = tags::read(&mut file)?;
+        let codec = Codec::open(&mut file, audio)?;
+        let sample_rate = codec.sample_rate();
+        let duration = codec
+            .length()
+            .map(|samples| PlaybackPosition::new(samples, sample_rate));
+
+        Ok(Self {
+            codec,
+            visualizer: Visualizer::default(),
+            track,
+            file,
+            logged_stats: (ReadStats::default(), Instant::now()),
+            read_rate: None,
+            tags,
+            position: PlaybackPosition::new(0, sample_rate),
+            duration,
+            loop_start: None,
+            loop_section: None,
+        })
+    }
+
+    pub fn track(&self) -> &'b Track {
+        self.track
+    }
+
+    pub fn tags(&self) -> &Tags {
+        &self.tags
+    }
+
+    /// Length of the track, known up front unless the stream does not say
+    pub fn duration(&self) -> Option<PlaybackPosition> {
+        self.duration
+    }
+
+    /// Reads of the track by the decoder, and those that went through to the card
+    pub fn read_stats(&self) -> ReadStats {
+        self.file.stats()
+    }
+
+    /// Read rate of the track with and without the read-ahead, over the last second it was
+    /// decoded
+    pub fn read_rate(&self) -> Option<ReadRate> {
+        self.read_rate
+    }
+
+    /// Position in the track, counted in decoded audio so it is unaffected by the playback speed
+    pub fn progress(&self) -> Progress {
+        Progress {
+            elapsed: self.position,
+            duration: self.duration,
+        }
+    }
+
+    /// Samples per channel decoded so far, the start of the next block
+    pub fn position(&self) -> PlaybackPosition {
+        self.position
+    }
+
+    pub fn loop_section(&self) -> Option<LoopSection> {
+        self.loop_section
+    }
+
+    /// Mark the A point of a loop at `at`, replacing any loop
+    pub fn mark_loop_start(&mut self, at: PlaybackPosition) {
+        self.loop_start = Some(at.at_rate(self.codec.sample_rate()));
+        self.loop_section = None;
+    }
+
+    /// Mark the B point of a loop at `at`, after which playback goes back to the A point
+    ///
+    /// Returns the loop, `None` if no A point was marked before `at`.
+    pub fn mark_loop_end(&mut self, at: PlaybackPosition) -> Option<LoopSection> {
+        let end = at.at_rate(self.codec.sample_rate());
+        let start = self
+            .loop_start
+            .filter(|start| start.samples() < end.samples())?;
+        self.loop_start = None;
+        self.loop_section = Some(LoopSection { start, end });
+        self.loop_section
+    }
+
+    /// Loop `section`, or stop looping for `None`
+    pub fn set_loop(&mut self, section: Option<LoopSection>) {
+        let sample_rate = self.codec.sample_rate();
+        self.loop_start = None;
+        // Kept at the rate of the track, as the decoder compares samples with it
+        self.loop_section = section
+            .map(|section| LoopSection {
+                start: section.start.at_rate(sample_rate),
+                end: section.end.at_rate(sample_rate),
+            })
+            .filter(|section| section.start.samples() < section.end.samples());
+    }
+
+    /// Native sample rate of the track
+    pub fn sample_rate(&self) -> u32 {
+        self.codec.sample_rate()
+    }
+
+    /// Jump to `position` in the track, which may be at another sample rate such as that of a
+    /// bookmark taken in another track
+    pub fn seek(&mut self, position: PlaybackPosition) -> Result<(), codec::Error<fs::Error>> {
+        self.seek_sample(position.at_rate(self.codec.sample_rate()).samples())
+    }
+
+    fn seek_sample(&mut self, target: u64) -> Result<(), codec::Error<fs::Error>> {
+        let reached = self.codec.seek(&mut self.file, target)?;
+        self.position = PlaybackPosition::new(reached, self.codec.sample_rate());
+        Ok(())
+    }
+
+    /// Decode the next frame into `pcm_buf`, returns `None` at the end of the track
+    fn next(&mut self, pcm_buf: &mut [f32]) -> Option<PcmInfo> {
+        // The block before ended right on the B point, or a seek went past it
+        if let Some(section) = self.loop_section {
+            if self.position.samples() >= section.end.samples() {
+                if let Err(err) = self.seek_sample(section.start.samples()) {
+                    log::warn!("Failed to loop {}: {:?}", self.track.title, err);
+                    self.loop_section = None;
+                }
+            }
+        }
+
+        let mut info = match self.codec.decode(&mut self.file, pcm_buf) {
+            Ok(info) => info?,
+            Err(err) => {
+                log::warn!("Failed to decode {}: {:?}", self.track.title, err);
+                return None;
+            }
+        };
+
+        // Cut the block at the B point so the loop joins on the exact sample
+        if let Some(section) = self.loop_section {
+            let left = section.end.saturating_sub(self.position);
+            info.frames = info.frames.min(left.samples() as usize);
+        }
+
+        // FFT
+        self.visualizer
+            .extend_with_channels(&pcm_buf[..info.frames * info.channels], info.channels);
+
+        self.position.advance(info.frames);
+        self.log_read_stats();
+        Some(info)
+    }
+
+    fn log_read_stats(&mut self) {
+        let (logged, at) = self.logged_stats;
+        let elapsed = at.elapsed();
+        if elapsed < READ_STATS_INTERVAL {
+            return;
+        }
+        let stats = self.file.stats();
+        let rate = stats.since(&logged).rate(elapsed.as_millis());
+        log::info!("{}: {}", self.track.title, rate);
+        self.read_rate = Some(rate);
+        self.logged_stats = (stats, Instant::now());
+    }
+}
+
+pub struct Player<'a, 'b> {
+    track: Option<TrackDecoder<'a, 'b>>,
+    queued: Option<TrackDecoder<'a, 'b>>,
+    output: OutputHandle<'a>,
+    state: PlaybackState,
+    volume: Volume,
+    normalizer: Normalizer,
+    effects: Chain<Effect, MAX_EFFECTS>,
+    stretch: TimeStretch,
+    resampler: Resampler,
+}
+
+impl<'a, 'b> Player<'a, 'b> {
+    /// Player decoding into the ring of an output task, see [`output::spawn_output_task`]
+    pub fn new(output: OutputHandle<'a>) -> Self {
+        let mut effects = Chain::new();
+        let equalizer = Equalizer::new(Preset::FLAT, OUTPUT_SAMPLE_RATE);
+        let _ = effects.push(Effect::Equalizer(equalizer));
+        let crossfeed = Crossfeed::new(CrossfeedLevel::Off, OUTPUT_SAMPLE_RATE);
+        let _ = effects.push(Effect::Crossfeed(crossfeed));
+        let _ = effects.push(Effect::Channels(ChannelMixer::default()));
+
+        let volume = Volume::default();
+        output.set_volume(volume);
+        Self {
+            track: None,
+            queued: None,
+            output,
+            state: PlaybackState::Stopped,
+            volume,
+            normalizer: Normalizer::default(),
+            effects,
+            stretch: TimeStretch::new(),
+            resampler: Resampler::new(OUTPUT_SAMPLE_RATE),
+        }
+    }
+
+    /// Start playing `track` from the beginning, fading out whatever was playing before
+    pub fn play(&mut self, track: TrackDecoder<'a, 'b>) {
+        self.normalizer.set_values(track.tags().replay_gain);
+        self.reset();
+        self.output.flush();
+        self.track = Some(track);
+        self.state = PlaybackState::Playing;
+        self.output.set_playing(true);
+    }
+
+    pub fn state(&self) -> PlaybackState {
+        self.state
+    }
+
+    /// Fade out and hold the position of the current track
+    pub fn pause(&mut self) {
+        if self.state == PlaybackState::Playing {
+            self.state = PlaybackState::Paused;
+            self.output.set_playing(false);
+        }
+    }
+
+    /// Fade back in where the track was paused
+    pub fn resume(&mut self) {
+        if self.state == PlaybackState::Paused {
+            self.state = PlaybackState::Playing;
+            self.output.set_playing(true);
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        match self.state {
+            PlaybackState::Playing => self.pause(),
+            PlaybackState::Paused => self.resume(),
+            PlaybackState::Stopped => {}
+        }
+    }
+
+    /// Fade out and close the current track, the queued track is kept
+    pub fn stop(&mut self) {
+        if self.state != PlaybackState::Stopped {
+            self.state = PlaybackState::Stopped;
+            self.track = None;
+            self.reset();
+            self.output.flush();
+            self.output.set_playing(false);
+        }
+    }
+
+    /// Open the track to play once the current one ends, so that it is spliced on without a gap
+    pub fn queue(&mut self, track: TrackDecoder<'a, 'b>) {
+        self.queued = Some(track)
+    }
+
+    pub fn has_queued(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    pub fn replay_gain_mode(&self) -> ReplayGainMode {
+        self.normalizer.mode()
+    }
+
+    pub fn set_replay_gain_mode(&mut self, mode: ReplayGainMode) {
+        self.normalizer.set_mode(mode)
+    }
+
+    pub fn volume(&self) -> Volume {
+        self.volume
+    }
+
+    /// Diagnostics of the output task
+    pub fn diagnostics(&self) -> Diagnostics {
+        self.output.diagnostics()
+    }
+
+    /// Set the volume in dB, clamped to [`volume::MIN_DB`] and [`volume::MAX_DB`]
+    pub fn set_volume_db(&mut self, db: f32) {
+        self.volume.set_db(db);
+        self.output.set_volume(self.volume);
+    }
+
+    /// Turn the volume up by `steps` of [`volume::STEP_DB`], or down for negative steps
+    pub fn step_volume(&mut self, steps: i32) {
+        self.volume.step(steps);
+        self.output.set_volume(self.volume);
+    }
+
+    pub fn mute(&mut self) {
+        self.volume.set_muted(true);
+        self.output.set_volume(self.volume);
+    }
+
+    pub fn unmute(&mut self) {
+        self.volume.set_muted(false);
+        self.output.set_volume(self.volume);
+    }
+
+    /// Effects run over decoded audio, their cost is in ticks of [`embassy_time::TICK_HZ`]
+    pub fn effects(&self) -> &Chain<Effect, MAX_EFFECTS> {
+        &self.effects
+    }
+
+    pub fn effects_mut(&mut self) -> &mut Chain<Effect, MAX_EFFECTS> {
+        &mut self.effects
+    }
+
+    /// Preset of the equalizer stage, `None` if it was taken out of the chain
+    pub fn eq_preset(&self) -> Option<&Preset> {
+        self.effects
+            .stages()
+            .iter()
+            .find_map(|stage| match stage.processor() {
+                Effect::Equalizer(equalizer) => Some(equalizer.preset()),
+                _ => None,
+            })
+    }
+
+    pub fn set_eq_preset(&mut self, preset: Preset) {
+        for stage in self.effects.stages_mut() {
+            if let Effect::Equalizer(equalizer) = stage.processor_mut() {
+                equalizer.set_preset(preset);
+            }
+        }
+    }
+
+    pub fn crossfeed(&self) -> CrossfeedLevel {
+        self.effects
+            .stages()
+            .iter()
+            .find_map(|stage| match stage.processor() {
+                Effect::Crossfeed(crossfeed) => Some(crossfeed.level()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn set_crossfeed(&mut self, level: CrossfeedLevel) {
+        for stage in self.effects.stages_mut() {
+            if let Effect::Crossfeed(crossfeed) = stage.processor_mut() {
+                crossfeed.set_level(level);
+            }
+        }
+    }
+
+    /// Channel mode and balance of the output
+    pub fn channel_mixer(&self) -> Option<&ChannelMixer> {
+        self.effects
+            .stages()
+            .iter()
+            .find_map(|stage| match stage.processor() {
+                Effect::Channels(mixer) => Some(mixer),
+                _ => None,
+            })
+    }
+
+    pub fn set_channel_mode(&mut self, mode: ChannelMode) {
+        for stage in self.effects.stages_mut() {
+            if let Effect::Channels(mixer) = stage.processor_mut() {
+                mixer.set_mode(mode);
+            }
+        }
+    }
+
+    /// Balance from -1 for left only to 1 for right only
+    pub fn set_balance(&mut self, balance: f32) {
+        for stage in self.effects.stages_mut() {
+            if let Effect::Channels(mixer) = stage.processor_mut() {
+                mixer.set_balance(balance);
+            }
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.stretch.speed()
+    }
+
+    /// Play faster or slower without changing the pitch, clamped to [`stretch::MIN_SPEED`] and
+    /// [`stretch::MAX_SPEED`]
+    pub fn set_speed(&mut self, speed: f32) {
+        self.stretch.set_speed(speed)
+    }
+
+    /// Mark the A point of a loop in the current track at what is heard now
+    pub fn mark_loop_start(&mut self) {
+        if let Some(at) = self.heard() {
+            if let Some(track) = self.track.as_mut() {
+                track.mark_loop_start(at);
+            }
+        }
+    }
+
+    /// Mark the B point of a loop in the current track at what is heard now and start looping,
+    /// see [`TrackDecoder::mark_loop_end`]
+    pub fn mark_loop_end(&mut self) -> Option<LoopSection> {
+        let at = self.heard()?;
+        self.track.as_mut()?.mark_loop_end(at)
+    }
+
+    /// Position in the current track of the audio coming out now, where a bookmark goes
+    ///
+    /// The decoder runs ahead by what waits in the output ring and the time stretcher.
+    pub fn heard(&self) -> Option<PlaybackPosition> {
+        let track = self.track.as_ref()?;
+        let sample_rate = track.sample_rate();
+        // Ring frames are at the output rate and already stretched to the playback speed
+        let queued = (self.output.queued() / 2) as f32 * sample_rate as f32
+            / OUTPUT_SAMPLE_RATE as f32
+            * self.stretch.speed();
+        let latency = queued as u64 + self.stretch.buffered() as u64;
+        Some(
+            track
+                .position()
+                .saturating_sub(PlaybackPosition::new(latency, sample_rate)),
+        )
+    }
+
+    pub fn clear_loop(&mut self) {
+        if let Some(track) = self.track.as_mut() {
+            track.set_loop(None);
+        }
+    }
+
+    pub fn loop_section(&self) -> Option<LoopSection> {
+        self.track.as_ref()?.loop_section()
+    }
+
+    /// Track being played, `None` if stopped
+    pub fn track(&self) -> Option<&'b Track> {
+        self.track.as_ref().map(TrackDecoder::track)
+    }
+
+    /// Progress of the current track, `None` if nothing is playing
+    pub fn progress(&self) -> Option<Progress> {
+        self.track.as_ref().map(TrackDecoder::progress)
+    }
+
+    /// Reads of the current track, see [`TrackDecoder::read_stats`]
+    pub fn read_stats(&self) -> Option<ReadStats> {
+        self.track.as_ref().map(TrackDecoder::read_stats)
+    }
+
+    /// Read rate of the current track, see [`TrackDecoder::read_rate`]
+    pub fn read_rate(&self) -> Option<ReadRate> {
+        self.track.as_ref()?.read_rate()
+    }
+
+    /// Jump to `position` in the current track
+    pub fn seek(&mut self, position: PlaybackPosition) -> Result<(), codec::Error<fs::Error>> {
+        let Some(track) = self.track.as_mut() else {
+            return Ok(());
+        };
+        track.seek(position)?;
+        // Filter history from before the jump would smear into the new position
+        self.reset();
+        self.output.flush();
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.effects.reset();
+        self.stretch.reset();
+        self.resampler.reset();
+    }
+
+    /// Decode the next frame of audio into the output ring, waiting while it is full
+    ///
+    /// Returns after a short wait when paused or stopped.
+    pub async fn next(&mut self) {
+        let mut pcm_buf = [0f32; MAX_SAMPLES];
+
+        if self.state != PlaybackState::Playing {
+            return Timer::after_millis(IDLE_MS).await;
+        }
+
+        loop {
+            if self.track.is_none() {
+                self.track = self.queued.take();
+                if let Some(track) = self.track.as_ref() {
+                    self.normalizer.set_values(track.tags().replay_gain);
+                }
+            }
+            let Some(track) = self.track.as_mut() else {
+                // Ran out of tracks, the output plays silence once the ring is empty
+                self.state = PlaybackState::Stopped;
+                return;
+            };
+
+            match track.next(&mut pcm_buf) {
+                Some(info) => {
+                    let pcm = &mut pcm_buf[..info.frames * info.channels];
+                    self.effects.configure(info.sample_rate, 2);
+                    self.stretch.configure(2);
+                    self.resampler.configure(info.sample_rate, 2);
+                    if info.channels == 2 {
+                        return self.process(pcm).await;
+                    }
+
+                    // Mono is sent to both sides before the effects, so the channel mode and
+                    // balance apply to it as well
+                    let mut stereo = [0f32; MAX_SAMPLES];
+                    for chunk in pcm.chunks(MAX_SAMPLES / 2) {
+                        for (frame, &sample) in stereo.chunks_exact_mut(2).zip(chunk) {
+                            frame.fill(sample);
+                        }
+                        self.process(&mut stereo[..chunk.len() * 2]).await;
+                    }
+                    return;
+                }
+                None => self.track = None,
+            }
+        }
+    }
+
+    /// Run the effects over stereo pcm and write it to the output
+    async fn process(&mut self, pcm_buf: &mut [f32]) {
+        self.effects.process(pcm_buf, &ticks);
+        self.write(pcm_buf).await
+    }
+
+    /// Write stereo pcm to the output, stretching it to the playback speed
+    async fn write(&mut self, pcm_buf: &mut [f32]) {
+        if self.stretch.is_passthrough() {
+            return self.resample(pcm_buf).await;
+        }
+
+        let mut pcm_buf: &[f32] = pcm_buf;
+        let mut out_buf = [0f32; MAX_CHANNELS * HOP];
+        loop {
+            let (consumed, produced) = self.stretch.process(pcm_buf, &mut out_buf);
+            if consumed == 0 && produced == 0 {
+                return;
+            }
+            pcm_buf = &pcm_buf[consumed..];
+            self.resample(&mut out_buf[..produced]).await;
+        }
+    }
+
+    /// Write stereo pcm to the output, resampling it to the output rate
+    async fn resample(&mut self, pcm_buf: &mut [f32]) {
+        if self.resampler.is_passthrough() {
+            return self.push(pcm_buf).await;
+        }
+
+        let mut pcm_buf: &[f32] = pcm_buf;
+        let mut out_buf = [0f32; MAX_SAMPLES];
+        while !pcm_buf.is_empty() {
+            let (consumed, produced) = self.resampler.process(pcm_buf, &mut out_buf);
+            pcm_buf = &pcm_buf[consumed..];
+            self.push(&mut out_buf[..produced]).await;
+        }
+    }
+
+    /// Apply ReplayGain and queue stereo pcm in the output ring
+    async fn push(&mut self, pcm_buf: &mut [f32]) {
+        let gain = self.normalizer.gain();
+        pcm_buf.iter_mut().for_each(|sample| *sample *= gain);
+        self.output.write(pcm_buf).await
+    }
+
+    pub fn sample_visualizer(&self) {
+        match self.track.as_ref() {
+            Some(track) => {
+                track.visualizer.sample(track.sample_rate() as f32);
+            }
+            None => {
+                Visualizer::default().sample(OUTPUT_SAMPLE_RATE as f32);
+            }
+        }
+    }
+}
+
+/// Time source for measuring the cost of effects
+fn ticks() -> u64 {
+    Instant::now().as_ticks()
+}
@@ -0,0 +1,180 @@
+//! Output task feeding the [`Sink`] from a ring buffer, so slow reads and decoding in the
+//! [`super::Player`] do not stall the I2S transfer
+//!
+//! The task runs on an interrupt executor at a higher priority than the player. SD card reads
+//! block the thread mode executor until they finish, and would starve the task otherwise.
+
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use embassy_executor::SendSpawner;
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embassy_time::Timer;
+use esp_hal::interrupt::{software::SoftwareInterrupt, Priority};
+use esp_hal_embassy::InterruptExecutor;
+use static_cell::StaticCell;
+
+use crate::codec::MAX_SAMPLES;
+
+use super::{
+    ring::{Consumer, Producer, Ring},
+    volume::Volume,
+    Diagnostics, I2sParts, Sink,
+};
+
+/// Stereo samples buffered between decoding and output, about 93 ms at 44.1 kHz
+pub const RING_SAMPLES: usize = 8192;
+/// Samples buffered before output starts, and again after the ring runs dry
+pub const DEFAULT_WATERMARK: usize = RING_SAMPLES / 2;
+
+/// Time the player waits for room when the ring is full
+const FULL_WAIT_MS: u64 = 2;
+/// Priority of the output executor, above the thread mode executor and below time critical
+/// interrupts
+const OUTPUT_PRIORITY: Priority = Priority::Priority3;
+
+static EXECUTOR: StaticCell<InterruptExecutor<2>> = StaticCell::new();
+
+type Shared<T> = Mutex<CriticalSectionRawMutex, Cell<T>>;
+
+/// Ring buffer, settings and diagnostics shared with the output task, meant to be a static
+///
+/// The player sets the state it wants rather than sending commands, so a change is never lost
+/// and only the latest one is applied.
+pub struct Output {
+    ring: Ring<RING_SAMPLES>,
+    /// Fade in and play from the ring, or fade out and hold what is left in it
+    playing: AtomicBool,
+    /// Volume to ramp to, `None` until the player sets one
+    volume: Shared<Option<Volume>>,
+    diagnostics: Shared<Diagnostics>,
+}
+
+impl Output {
+    pub const fn new() -> Self {
+        Self {
+            ring: Ring::new(),
+            playing: AtomicBool::new(false),
+            volume: Mutex::new(Cell::new(None)),
+            diagnostics: Mutex::new(Cell::new(Diagnostics {
+                limited_samples: 0,
+                underruns: 0,
+            })),
+        }
+    }
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Writing end of the output, held by the player
+pub struct OutputHandle<'a> {
+    producer: Producer<'a, RING_SAMPLES>,
+    output: &'a Output,
+}
+
+impl OutputHandle<'_> {
+    /// Queue interleaved stereo `pcm`, waiting while the ring is full
+    pub async fn write(&mut self, mut pcm: &[f32]) {
+        while !pcm.is_empty() {
+            let n = self.producer.write(pcm);
+            pcm = &pcm[n..];
+            if n == 0 {
+                Timer::after_millis(FULL_WAIT_MS).await;
+            }
+        }
+    }
+
+    /// Samples written and not played yet
+    pub fn queued(&self) -> usize {
+        self.output.ring.len()
+    }
+
+    /// Fade out and drop everything queued so far, the audio written next fades in
+    pub fn flush(&mut self) {
+        self.producer.flush()
+    }
+
+    /// Fade in and play from the ring, or fade out and hold what is left in it
+    pub fn set_playing(&self, playing: bool) {
+        self.output.playing.store(playing, Ordering::Release)
+    }
+
+    /// Ramp the output to `volume`
+    pub fn set_volume(&self, volume: Volume) {
+        self.output.volume.lock(|shared| shared.set(Some(volume)))
+    }
+
+    /// Diagnostics of the sink as of its last write
+    pub fn diagnostics(&self) -> Diagnostics {
+        self.output.diagnostics.lock(Cell::get)
+    }
+}
+
+/// Start the executor the output task runs on, which preempts the player whenever the I2S
+/// transfer needs more audio
+pub fn start_output_executor(interrupt: SoftwareInterrupt<'static, 2>) -> SendSpawner {
+    EXECUTOR
+        .init(InterruptExecutor::new(interrupt))
+        .start(OUTPUT_PRIORITY)
+}
+
+/// Spawn the output task playing what is written to the returned handle through a [`Sink`] on
+/// `parts`
+///
+/// `spawner` is that of [`start_output_executor`]. The sink is started by the task, as its
+/// transfer cannot be sent to another executor. Output starts once `watermark` samples are
+/// queued, and pauses to build them up again if the ring runs dry.
+pub fn spawn_output_task(
+    spawner: SendSpawner,
+    output: &'static Output,
+    parts: I2sParts<'static>,
+    watermark: usize,
+) -> OutputHandle<'static> {
+    let (producer, mut consumer) = output.ring.split().expect("Output task already spawned");
+    consumer.set_watermark(watermark);
+    spawner.must_spawn(output_task(parts, consumer, output));
+    OutputHandle { producer, output }
+}
+
+#[embassy_executor::task]
+async fn output_task(
+    parts: I2sParts<'static>,
+    mut consumer: Consumer<'static, RING_SAMPLES>,
+    output: &'static Output,
+) -> ! {
+    let mut sink = Sink::new(parts).expect("Failed to start the I2S transfer");
+    let mut pcm_buf = [0f32; MAX_SAMPLES];
+
+    loop {
+        if let Some(volume) = output.volume.lock(Cell::get) {
+            sink.set_volume(volume);
+        }
+
+        // Audio before a flush is faded out rather than cut off
+        let playing = output.playing.load(Ordering::Acquire);
+        let flushing = consumer.flush_pending().is_some();
+        sink.fade(playing && !flushing);
+
+        let result = if sink.is_faded_out() {
+            consumer.flush();
+            sink.write_silence().await
+        } else {
+            match consumer.read(&mut pcm_buf) {
+                0 => sink.write_silence().await,
+                n => sink.write_frame(&mut pcm_buf[..n]).await,
+            }
+        };
+        if let Err(err) = result {
+            log::warn!("Failed to write to I2S: {:?}", err);
+        }
+        output
+            .diagnostics
+            .lock(|shared| shared.set(sink.diagnostics()));
+    }
+}
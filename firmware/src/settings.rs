@@ -0,0 +1,51 @@
+//! Player settings kept on the SD card between power cycles
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fs::{decode, encode, EncodeError, FileSystem},
+    player::{crossfeed::CrossfeedLevel, Player},
+};
+
+/// File the settings are saved in, in the root of the SD card
+const SETTINGS_FILE: &str = "settings.post";
+
+/// Serde mirror of [`CrossfeedLevel`], which lives in the core crate without serde
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "CrossfeedLevel")]
+enum CrossfeedLevelDef {
+    Off,
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    #[serde(with = "CrossfeedLevelDef")]
+    pub crossfeed: CrossfeedLevel,
+}
+
+impl Settings {
+    /// Read the saved settings, the defaults if there are none or they cannot be read
+    pub fn load<'a>(fs: &'a FileSystem<'a>) -> Self {
+        match fs.open_file(SETTINGS_FILE) {
+            Ok(file) => decode(file).unwrap_or_else(|err| {
+                log::warn!("Failed to read settings: {:?}", err);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save<'a>(&self, fs: &'a FileSystem<'a>) -> Result<(), EncodeError> {
+        let file = fs
+            .create_file(SETTINGS_FILE)
+            .map_err(|_| EncodeError::Write)?;
+        encode(file, self)
+    }
+
+    pub fn apply(&self, player: &mut Player) {
+        player.set_crossfeed(self.crossfeed);
+    }
+}
@@ -1,11 +1,18 @@
+use core::fmt::Write;
+
 use embedded_menu::{
     items::{menu_item::SelectValue, MenuItem},
     MenuStyle, SelectValue,
 };
-use heapless::{Vec, VecView};
+use heapless::{String, Vec, VecView};
 use pmp_config::{Playlist, Track};
 
-use crate::input::Receiver;
+use crate::{
+    fs::FileSystem,
+    input::Receiver,
+    player::{crossfeed::CrossfeedLevel, Player, Progress},
+    settings::Settings,
+};
 
 struct ListState {
     index: usize,
@@ -81,6 +88,47 @@ impl<'a> SelectValue for Command<'a> {
     }
 }
 
+/// Value of a settings item once changed
+#[derive(Clone, Copy, PartialEq)]
+enum Setting {
+    Crossfeed(CrossfeedLevel),
+}
+
+impl Setting {
+    /// Apply the changed setting to the player and save it for the next power up
+    fn change<'a>(self, settings: &mut Settings, player: &mut Player, fs: &'a FileSystem<'a>) {
+        match self {
+            Self::Crossfeed(level) => settings.crossfeed = level,
+        }
+        settings.apply(player);
+        if let Err(err) = settings.save(fs) {
+            log::warn!("Failed to save settings: {:?}", err);
+        }
+    }
+}
+
+impl SelectValue for CrossfeedLevel {
+    fn next(&mut self) {
+        *self = self.cycle();
+    }
+
+    fn marker(&self) -> &str {
+        self.name()
+    }
+}
+
+/// Settings item stepping through the crossfeed levels on select
+fn crossfeed_item(level: CrossfeedLevel) -> MenuItem<&'static str, Setting, CrossfeedLevel, true> {
+    MenuItem::new("Crossfeed", level).with_value_converter(Setting::Crossfeed)
+}
+
+/// Items of the settings menu, showing the current `settings`
+fn settings_items(
+    settings: &Settings,
+) -> [MenuItem<&'static str, Setting, CrossfeedLevel, true>; 1] {
+    [crossfeed_item(settings.crossfeed)]
+}
+
 fn ply_menu(ply: Playlist) -> Playlist {
     let mut ls = [MenuItem::new("", ()).with_value_converter(|_| Command::Play)];
     let mut a = embedded_menu::Menu::build(ply.title.as_str())
@@ -109,8 +157,42 @@ fn track_menu(playlist: Playlist) {
     // let mut menu = embedded_menu::Menu::build("Tracks").add_menu_items(playlist.tracks);
 }
 
+/// Now playing screen
+struct NowPlaying {
+    progress: Progress,
+}
+
+impl NowPlaying {
+    /// Screen for the current track of `player`, `None` if nothing is playing
+    fn new(player: &Player) -> Option<Self> {
+        player.progress().map(|progress| Self { progress })
+    }
+
+    /// Time played as `mm:ss`
+    fn elapsed(&self) -> String<8> {
+        let mut label = String::new();
+        let _ = write!(label, "{}", self.progress.elapsed);
+        label
+    }
+
+    /// Time left as `-mm:ss`, `None` if the duration of the track is unknown
+    fn remaining(&self) -> Option<String<9>> {
+        let remaining = self.progress.remaining()?;
+        let mut label = String::new();
+        let _ = write!(label, "-{}", remaining);
+        Some(label)
+    }
+
+    /// Filled width of a `width` pixel wide progress bar, `None` when the bar should be hidden
+    /// because the duration of the track is unknown
+    fn bar_width(&self, width: u32) -> Option<u32> {
+        self.progress
+            .fraction()
+            .map(|fraction| (fraction * width as f32) as u32)
+    }
+}
+
 // make methods to build each menu
-// make seperate thing for displaying audio progress
 // make seperate thing for fft
 
 // what if i made a scrolling view over a contiguous array of elements
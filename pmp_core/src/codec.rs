@@ -0,0 +1,164 @@
+use core::ops::Range;
+
+use crate::fs::Source;
+
+use self::{flac::FlacDecoder, mp3::Mp3Decoder, wav::WavDecoder};
+
+pub mod flac;
+pub mod mp3;
+pub mod wav;
+
+/// Most interleaved samples produced by a single call to [`AudioDecoder::decode`]
+pub const MAX_SAMPLES: usize = nanomp3::MAX_SAMPLES_PER_FRAME;
+
+/// Format of a block of decoded pcm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcmInfo {
+    /// Samples per channel
+    pub frames: usize,
+    pub channels: usize,
+    pub sample_rate: u32,
+}
+
+/// Codec Errors
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    /// Reading from the source failed
+    Read(E),
+    /// The stream is not in a supported format
+    Unsupported,
+    /// The stream is corrupt
+    Malformed,
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(err: E) -> Self {
+        Self::Read(err)
+    }
+}
+
+/// Decoder turning an encoded stream into interleaved float pcm
+///
+/// Decoders read from the [`Source`] they were opened on, so positions and lengths are in
+/// samples per channel of the decoded output.
+pub trait AudioDecoder {
+    /// Decode the next block of the stream into `pcm`, which holds at least [`MAX_SAMPLES`]
+    ///
+    /// Returns `None` at the end of the stream.
+    fn decode<S: Source>(
+        &mut self,
+        source: &mut S,
+        pcm: &mut [f32],
+    ) -> Result<Option<PcmInfo>, Error<S::Error>>;
+
+    /// Continue decoding from sample `position`, returns the position reached which is only
+    /// approximate if the stream cannot be seeked accurately
+    fn seek<S: Source>(&mut self, source: &mut S, position: u64) -> Result<u64, Error<S::Error>>;
+
+    fn sample_rate(&self) -> u32;
+
+    fn channels(&self) -> usize;
+
+    /// Total samples per channel, if known
+    fn length(&self) -> Option<u64>;
+}
+
+/// Supported stream formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Flac,
+    Mp3,
+    Wav,
+}
+
+impl Format {
+    /// Detect the format from the first bytes of the audio data
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        match header {
+            [b'f', b'L', b'a', b'C', ..] => Some(Self::Flac),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some(Self::Wav),
+            [0xFF, second, ..] if second & 0xE0 == 0xE0 => Some(Self::Mp3),
+            _ => None,
+        }
+    }
+}
+
+/// Decoder for any supported format
+///
+/// There is no allocator, so every decoder is stored inline.
+#[allow(clippy::large_enum_variant)]
+pub enum Codec {
+    Flac(FlacDecoder),
+    Mp3(Mp3Decoder),
+    Wav(WavDecoder),
+}
+
+impl Codec {
+    /// Detect the format of the audio in `audio` and open a decoder for it
+    pub fn open<S: Source>(source: &mut S, audio: Range<u32>) -> Result<Self, Error<S::Error>> {
+        let mut header = [0u8; 12];
+        source.seek_from_start(audio.start)?;
+        let read = source.read_full(&mut header)?;
+
+        Ok(match Format::detect(&header[..read]) {
+            Some(Format::Flac) => Self::Flac(FlacDecoder::open(source, audio)?),
+            Some(Format::Wav) => Self::Wav(WavDecoder::open(source, audio)?),
+            // Also try to sync to MP3 frames after leading junk
+            Some(Format::Mp3) | None => Self::Mp3(Mp3Decoder::open(source, audio)?),
+        })
+    }
+
+    pub fn format(&self) -> Format {
+        match self {
+            Self::Flac(_) => Format::Flac,
+            Self::Mp3(_) => Format::Mp3,
+            Self::Wav(_) => Format::Wav,
+        }
+    }
+}
+
+impl AudioDecoder for Codec {
+    fn decode<S: Source>(
+        &mut self,
+        source: &mut S,
+        pcm: &mut [f32],
+    ) -> Result<Option<PcmInfo>, Error<S::Error>> {
+        match self {
+            Self::Flac(decoder) => decoder.decode(source, pcm),
+            Self::Mp3(decoder) => decoder.decode(source, pcm),
+            Self::Wav(decoder) => decoder.decode(source, pcm),
+        }
+    }
+
+    fn seek<S: Source>(&mut self, source: &mut S, position: u64) -> Result<u64, Error<S::Error>> {
+        match self {
+            Self::Flac(decoder) => decoder.seek(source, position),
+            Self::Mp3(decoder) => decoder.seek(source, position),
+            Self::Wav(decoder) => decoder.seek(source, position),
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        match self {
+            Self::Flac(decoder) => decoder.sample_rate(),
+            Self::Mp3(decoder) => decoder.sample_rate(),
+            Self::Wav(decoder) => decoder.sample_rate(),
+        }
+    }
+
+    fn channels(&self) -> usize {
+        match self {
+            Self::Flac(decoder) => decoder.channels(),
+            Self::Mp3(decoder) => decoder.channels(),
+            Self::Wav(decoder) => decoder.channels(),
+        }
+    }
+
+    fn length(&self) -> Option<u64> {
+        match self {
+            Self::Flac(decoder) => decoder.length(),
+            Self::Mp3(decoder) => decoder.length(),
+            Self::Wav(decoder) => decoder.length(),
+        }
+    }
+}
@@ -0,0 +1,923 @@
+//! FLAC decoder for the streamable subset at up to 48 kHz
+//!
+//! A whole block has to be decoded before any of it can be played, which takes
+//! [`MAX_BLOCK_SIZE`] samples for each of [`MAX_CHANNELS`], 36 KiB in total. All decoders share
+//! one static block and only keep their position in it, so a decoder whose block was
+//! overwritten by another one decodes its current frame again. Streams with larger blocks are
+//! rejected as unsupported.
+
+use core::ops::Range;
+
+use byteorder::{BigEndian, ByteOrder};
+use heapless::Vec;
+
+use crate::{
+    fs::Source,
+    shared::{Share, SharedBuffer},
+};
+
+use super::{AudioDecoder, Error, Format, PcmInfo, MAX_SAMPLES};
+
+/// Largest block allowed in the streamable subset at up to 48 kHz
+pub const MAX_BLOCK_SIZE: usize = 4608;
+pub const MAX_CHANNELS: usize = 2;
+pub const MAX_SAMPLE_RATE: u32 = 48000;
+const MAX_BITS_PER_SAMPLE: u32 = 24;
+const MAX_LPC_ORDER: usize = 32;
+
+/// Seek points kept from the seek table, larger tables are thinned out
+const MAX_SEEK_POINTS: usize = 64;
+/// Bisection steps when seeking between two known frames
+const BISECT_STEPS: u32 = 16;
+/// Bytes searched for a frame header before giving up
+const SYNC_LIMIT: u32 = 64 * 1024;
+
+const BLOCK_STREAMINFO: u8 = 0;
+const BLOCK_SEEKTABLE: u8 = 3;
+const BLOCK_INVALID: u8 = 127;
+
+/// Decoded samples of a frame for each channel
+type Block = [[i32; MAX_BLOCK_SIZE]; MAX_CHANNELS];
+
+static BLOCK: SharedBuffer<Block> = SharedBuffer::new([[0; MAX_BLOCK_SIZE]; MAX_CHANNELS]);
+
+const CRC8: [u8; 256] = crc8_table();
+const CRC16: [u16; 256] = crc16_table();
+
+/// CRC-8 with polynomial x^8 + x^2 + x + 1, protecting frame headers
+const fn crc8_table() -> [u8; 256] {
+    let mut table = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// CRC-16 with polynomial x^16 + x^15 + x^2 + 1, protecting whole frames
+const fn crc16_table() -> [u16; 256] {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Contents of the STREAMINFO metadata block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamInfo {
+    pub min_block_size: u16,
+    pub max_block_size: u16,
+    pub sample_rate: u32,
+    pub channels: u8,
+    pub bits_per_sample: u8,
+    /// Samples per channel, 0 if unknown
+    pub total_samples: u64,
+}
+
+impl StreamInfo {
+    pub fn parse(block: &[u8; 34]) -> Self {
+        Self {
+            min_block_size: BigEndian::read_u16(&block[0..]),
+            max_block_size: BigEndian::read_u16(&block[2..]),
+            sample_rate: BigEndian::read_u24(&block[10..]) >> 4,
+            channels: ((block[12] >> 1) & 0x07) + 1,
+            bits_per_sample: (((block[12] & 0x01) << 4) | (block[13] >> 4)) + 1,
+            total_samples: (u64::from(block[13] & 0x0F) << 32)
+                | u64::from(BigEndian::read_u32(&block[14..])),
+        }
+    }
+}
+
+/// Entry in the seek table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeekPoint {
+    /// First sample of the frame
+    pub sample: u64,
+    /// Offset of the frame from the first frame
+    pub offset: u32,
+}
+
+/// How the channels of a frame are coded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channels {
+    Independent(u8),
+    /// Left and side, right is left - side
+    LeftSide,
+    /// Side and right, left is side + right
+    RightSide,
+    /// Mid and side
+    MidSide,
+}
+
+impl Channels {
+    pub fn count(self) -> usize {
+        match self {
+            Self::Independent(channels) => channels.into(),
+            _ => 2,
+        }
+    }
+
+    /// Side channels carry one extra bit per sample
+    fn is_side(self, channel: usize) -> bool {
+        matches!(
+            (self, channel),
+            (Self::LeftSide, 1) | (Self::RightSide, 0) | (Self::MidSide, 1)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    /// Samples per channel
+    pub block_size: usize,
+    pub sample_rate: u32,
+    pub channels: Channels,
+    pub bits_per_sample: u32,
+    pub first_sample: u64,
+}
+
+impl FrameHeader {
+    fn read<S: Source>(
+        reader: &mut BitReader<S>,
+        info: &StreamInfo,
+    ) -> Result<Self, Error<S::Error>> {
+        reader.buffer.crc8 = 0;
+        reader.buffer.crc16 = 0;
+
+        // Sync code followed by a reserved zero bit
+        if reader.read(15)? != 0x7FFC {
+            return Err(Error::Malformed);
+        }
+        let variable = reader.read(1)? == 1;
+        let block_code = reader.read(4)?;
+        let rate_code = reader.read(4)?;
+        let channel_code = reader.read(4)?;
+        let size_code = reader.read(3)?;
+        if reader.read(1)? != 0 {
+            return Err(Error::Malformed);
+        }
+        let number = reader.read_utf8()?;
+
+        let block_size = match block_code {
+            0 => return Err(Error::Malformed),
+            1 => 192,
+            2..=5 => 576 << (block_code - 2),
+            6 => reader.read(8)? + 1,
+            7 => reader.read(16)? + 1,
+            _ => 256 << (block_code - 8),
+        };
+        let sample_rate = match rate_code {
+            0 => info.sample_rate,
+            1 => 88200,
+            2 => 176400,
+            3 => 192000,
+            4 => 8000,
+            5 => 16000,
+            6 => 22050,
+            7 => 24000,
+            8 => 32000,
+            9 => 44100,
+            10 => 48000,
+            11 => 96000,
+            12 => reader.read(8)? * 1000,
+            13 => reader.read(16)?,
+            14 => reader.read(16)? * 10,
+            _ => return Err(Error::Malformed),
+        };
+        let bits_per_sample = match size_code {
+            0 => info.bits_per_sample.into(),
+            1 => 8,
+            2 => 12,
+            4 => 16,
+            5 => 20,
+            6 => 24,
+            7 => 32,
+            _ => return Err(Error::Malformed),
+        };
+        let channels = match channel_code {
+            0..=7 => Channels::Independent(channel_code as u8 + 1),
+            8 => Channels::LeftSide,
+            9 => Channels::RightSide,
+            10 => Channels::MidSide,
+            _ => return Err(Error::Malformed),
+        };
+
+        let crc = reader.buffer.crc8;
+        if reader.read(8)? != u32::from(crc) {
+            return Err(Error::Malformed);
+        }
+
+        Ok(Self {
+            block_size: block_size as usize,
+            sample_rate,
+            channels,
+            bits_per_sample,
+            first_sample: match variable {
+                true => number,
+                false => number * u64::from(info.max_block_size),
+            },
+        })
+    }
+
+    /// Whether the header agrees with the stream, to reject false syncs when seeking
+    fn is_consistent(&self, info: &StreamInfo) -> bool {
+        self.sample_rate == info.sample_rate
+            && self.bits_per_sample == u32::from(info.bits_per_sample)
+            && self.channels.count() == usize::from(info.channels)
+            && self.block_size <= usize::from(info.max_block_size)
+    }
+}
+
+/// Read buffer and CRC state that persists between frames
+struct BitBuffer {
+    buf: [u8; 256],
+    pos: usize,
+    len: usize,
+    /// Never read at or past this offset
+    end: u32,
+    /// Bits fetched from `buf`, only the low `count` are unread
+    bits: u64,
+    count: u32,
+    crc8: u8,
+    crc16: u16,
+}
+
+impl BitBuffer {
+    fn new(end: u32) -> Self {
+        Self {
+            buf: [0u8; 256],
+            pos: 0,
+            len: 0,
+            end,
+            bits: 0,
+            count: 0,
+            crc8: 0,
+            crc16: 0,
+        }
+    }
+
+    /// Drop buffered data, after the source has been seeked
+    fn reset(&mut self) {
+        self.pos = 0;
+        self.len = 0;
+        self.count = 0;
+    }
+
+    /// Offset of the next unread byte, at a byte boundary
+    fn offset<S: Source>(&self, source: &S) -> u32 {
+        source.offset() - (self.len - self.pos) as u32 - self.count / 8
+    }
+}
+
+/// Big endian bit reader over a [`Source`]
+///
+/// Bytes are only fetched when needed, so that the CRCs cover exactly the bytes consumed.
+struct BitReader<'a, S> {
+    buffer: &'a mut BitBuffer,
+    source: &'a mut S,
+}
+
+impl<S: Source> BitReader<'_, S> {
+    fn byte(&mut self) -> Result<u8, Error<S::Error>> {
+        let buffer = &mut *self.buffer;
+        if buffer.pos == buffer.len {
+            let len =
+                (buffer.end.saturating_sub(self.source.offset()) as usize).min(buffer.buf.len());
+            buffer.pos = 0;
+            buffer.len = match len {
+                0 => 0,
+                len => self.source.read(&mut buffer.buf[..len])?,
+            };
+            // Frames never run past the end of the audio
+            if buffer.len == 0 {
+                return Err(Error::Malformed);
+            }
+        }
+
+        let byte = buffer.buf[buffer.pos];
+        buffer.pos += 1;
+        buffer.crc8 = CRC8[usize::from(buffer.crc8 ^ byte)];
+        buffer.crc16 = (buffer.crc16 << 8) ^ CRC16[usize::from((buffer.crc16 >> 8) as u8 ^ byte)];
+        Ok(byte)
+    }
+
+    /// Read an unsigned value of up to 32 bits
+    fn read(&mut self, bits: u32) -> Result<u32, Error<S::Error>> {
+        if bits == 0 {
+            return Ok(0);
+        }
+        while self.buffer.count < bits {
+            let byte = self.byte()?;
+            self.buffer.bits = (self.buffer.bits << 8) | u64::from(byte);
+            self.buffer.count += 8;
+        }
+        self.buffer.count -= bits;
+        Ok((self.buffer.bits >> self.buffer.count) as u32 & (u32::MAX >> (32 - bits)))
+    }
+
+    /// Read a two's complement value of up to 32 bits
+    fn read_signed(&mut self, bits: u32) -> Result<i32, Error<S::Error>> {
+        match bits {
+            0 => Ok(0),
+            bits => Ok(((self.read(bits)? << (32 - bits)) as i32) >> (32 - bits)),
+        }
+    }
+
+    /// Count zero bits up to the next one bit
+    fn read_unary(&mut self) -> Result<u32, Error<S::Error>> {
+        let mut zeros = 0;
+        loop {
+            if self.buffer.count == 0 {
+                self.buffer.bits = self.byte()?.into();
+                self.buffer.count = 8;
+            }
+            let unread = self.buffer.bits & ((1 << self.buffer.count) - 1);
+            if unread == 0 {
+                zeros += self.buffer.count;
+                self.buffer.count = 0;
+                continue;
+            }
+            let one = 63 - unread.leading_zeros();
+            zeros += self.buffer.count - 1 - one;
+            self.buffer.count = one;
+            return Ok(zeros);
+        }
+    }
+
+    /// Read a Rice coded residual with parameter `k`
+    fn read_rice(&mut self, k: u32) -> Result<i32, Error<S::Error>> {
+        let quotient = self.read_unary()?;
+        let value = quotient.wrapping_shl(k) | self.read(k)?;
+        Ok((value >> 1) as i32 ^ -((value & 1) as i32))
+    }
+
+    /// Read a frame or sample number, coded like UTF-8 extended to 36 bits
+    fn read_utf8(&mut self) -> Result<u64, Error<S::Error>> {
+        let first = self.read(8)?;
+        let len = match (first as u8).leading_ones() {
+            0 => return Ok(first.into()),
+            1 | 8 => return Err(Error::Malformed),
+            len => len,
+        };
+
+        let mut value = u64::from(first & (0x7F >> len));
+        for _ in 1..len {
+            let byte = self.read(8)?;
+            if byte & 0xC0 != 0x80 {
+                return Err(Error::Malformed);
+            }
+            value = (value << 6) | u64::from(byte & 0x3F);
+        }
+        Ok(value)
+    }
+
+    /// Skip to the next byte boundary
+    fn align(&mut self) {
+        self.buffer.count -= self.buffer.count % 8;
+    }
+}
+
+/// Decode one subframe of `bits` bits per sample into `out`
+fn read_subframe<S: Source>(
+    reader: &mut BitReader<S>,
+    out: &mut [i32],
+    bits: u32,
+) -> Result<(), Error<S::Error>> {
+    if reader.read(1)? != 0 {
+        return Err(Error::Malformed);
+    }
+    let kind = reader.read(6)?;
+    let wasted = match reader.read(1)? {
+        1 => reader.read_unary()? + 1,
+        _ => 0,
+    };
+    if wasted >= bits {
+        return Err(Error::Malformed);
+    }
+    let bits = bits - wasted;
+
+    match kind {
+        // Constant
+        0 => out.fill(reader.read_signed(bits)?),
+        // Verbatim
+        1 => {
+            for sample in out.iter_mut() {
+                *sample = reader.read_signed(bits)?;
+            }
+        }
+        8..=12 => read_fixed(reader, out, bits, (kind - 8) as usize)?,
+        32..=63 => read_lpc(reader, out, bits, (kind - 31) as usize)?,
+        _ => return Err(Error::Malformed),
+    }
+
+    if wasted > 0 {
+        for sample in out.iter_mut() {
+            *sample <<= wasted;
+        }
+    }
+    Ok(())
+}
+
+fn read_warmup<S: Source>(
+    reader: &mut BitReader<S>,
+    out: &mut [i32],
+    bits: u32,
+    order: usize,
+) -> Result<(), Error<S::Error>> {
+    if order > out.len() {
+        return Err(Error::Malformed);
+    }
+    for sample in &mut out[..order] {
+        *sample = reader.read_signed(bits)?;
+    }
+    Ok(())
+}
+
+/// Fixed polynomial predictor of order 0 to 4
+fn read_fixed<S: Source>(
+    reader: &mut BitReader<S>,
+    out: &mut [i32],
+    bits: u32,
+    order: usize,
+) -> Result<(), Error<S::Error>> {
+    read_warmup(reader, out, bits, order)?;
+    read_residual(reader, out, order)?;
+
+    for i in order..out.len() {
+        let sample = |delay: usize| i64::from(out[i - delay]);
+        let prediction = match order {
+            0 => 0,
+            1 => sample(1),
+            2 => 2 * sample(1) - sample(2),
+            3 => 3 * (sample(1) - sample(2)) + sample(3),
+            _ => 4 * (sample(1) + sample(3)) - 6 * sample(2) - sample(4),
+        };
+        out[i] = out[i].wrapping_add(prediction as i32);
+    }
+    Ok(())
+}
+
+/// Linear predictor with quantised coefficients
+fn read_lpc<S: Source>(
+    reader: &mut BitReader<S>,
+    out: &mut [i32],
+    bits: u32,
+    order: usize,
+) -> Result<(), Error<S::Error>> {
+    read_warmup(reader, out, bits, order)?;
+
+    let precision = reader.read(4)? + 1;
+    let shift = reader.read_signed(5)?;
+    if precision > 15 || shift < 0 {
+        return Err(Error::Malformed);
+    }
+    let mut coefficients = [0i32; MAX_LPC_ORDER];
+    for coefficient in &mut coefficients[..order] {
+        *coefficient = reader.read_signed(precision)?;
+    }
+    read_residual(reader, out, order)?;
+
+    let coefficients = &coefficients[..order];
+    for i in order..out.len() {
+        let prediction: i64 = coefficients
+            .iter()
+            .zip(out[i - order..i].iter().rev())
+            .map(|(&coefficient, &sample)| i64::from(coefficient) * i64::from(sample))
+            .sum();
+        out[i] = out[i].wrapping_add((prediction >> shift) as i32);
+    }
+    Ok(())
+}
+
+/// Read the partitioned Rice coded residual into `out[order..]`
+fn read_residual<S: Source>(
+    reader: &mut BitReader<S>,
+    out: &mut [i32],
+    order: usize,
+) -> Result<(), Error<S::Error>> {
+    let parameter_bits = match reader.read(2)? {
+        0 => 4,
+        1 => 5,
+        _ => return Err(Error::Malformed),
+    };
+    let escape = (1 << parameter_bits) - 1;
+
+    let partition_order = reader.read(4)?;
+    let partition_len = out.len() >> partition_order;
+    if partition_len << partition_order != out.len() || partition_len < order {
+        return Err(Error::Malformed);
+    }
+
+    let mut start = order;
+    for end in (1..=1 << partition_order).map(|partition| partition * partition_len) {
+        let parameter = reader.read(parameter_bits)?;
+        if parameter == escape {
+            let bits = reader.read(5)?;
+            for sample in &mut out[start..end] {
+                *sample = reader.read_signed(bits)?;
+            }
+        } else {
+            for sample in &mut out[start..end] {
+                *sample = reader.read_rice(parameter)?;
+            }
+        }
+        start = end;
+    }
+    Ok(())
+}
+
+/// Undo inter-channel decorrelation of the first `len` samples
+fn decorrelate(channels: Channels, block: &mut Block, len: usize) {
+    let [left, right] = block;
+    let samples = left[..len].iter_mut().zip(right[..len].iter_mut());
+    match channels {
+        Channels::Independent(_) => {}
+        Channels::LeftSide => {
+            for (left, side) in samples {
+                *side = left.wrapping_sub(*side);
+            }
+        }
+        Channels::RightSide => {
+            for (side, right) in samples {
+                *side = side.wrapping_add(*right);
+            }
+        }
+        Channels::MidSide => {
+            for (mid, side) in samples {
+                let sum = (*mid << 1) | (*side & 1);
+                *mid = sum.wrapping_add(*side) >> 1;
+                *side = sum.wrapping_sub(*side) >> 1;
+            }
+        }
+    }
+}
+
+/// Free Lossless Audio Codec decoder
+pub struct FlacDecoder {
+    info: StreamInfo,
+    seek_table: Vec<SeekPoint, MAX_SEEK_POINTS>,
+    /// Offset of the first frame
+    audio_start: u32,
+    buffer: BitBuffer,
+    /// Decoded samples of the current frame, in the block shared by all decoders
+    block: Share<'static, Block>,
+    /// Offset of the current frame, to decode it again if the block was overwritten
+    frame_offset: u32,
+    block_len: usize,
+    /// Next sample of `block` to output
+    block_pos: usize,
+    sample_rate: u32,
+    channels: usize,
+    bits_per_sample: u32,
+    /// Sample to resume at after a seek, frames before it are dropped
+    seek_target: Option<u64>,
+}
+
+impl FlacDecoder {
+    /// Read the metadata blocks in `audio`, stopping at the first frame
+    pub fn open<S: Source>(source: &mut S, audio: Range<u32>) -> Result<Self, Error<S::Error>> {
+        let mut marker = [0u8; 4];
+        source.seek_from_start(audio.start)?;
+        let read = source.read_full(&mut marker)?;
+        if Format::detect(&marker[..read]) != Some(Format::Flac) {
+            return Err(Error::Unsupported);
+        }
+
+        let mut info = None;
+        let mut seek_table = Vec::new();
+        let mut offset = audio.start + 4;
+        loop {
+            let mut header = [0u8; 4];
+            source.seek_from_start(offset)?;
+            if source.read_full(&mut header)? < header.len() {
+                return Err(Error::Malformed);
+            }
+            let len = BigEndian::read_u24(&header[1..]);
+
+            match header[0] & 0x7F {
+                BLOCK_STREAMINFO => {
+                    let mut block = [0u8; 34];
+                    if len < 34 || source.read_full(&mut block)? < block.len() {
+                        return Err(Error::Malformed);
+                    }
+                    info = Some(StreamInfo::parse(&block));
+                }
+                BLOCK_SEEKTABLE => seek_table = Self::read_seek_table(source, len)?,
+                BLOCK_INVALID => return Err(Error::Malformed),
+                _ => {}
+            }
+
+            offset += 4 + len;
+            if header[0] & 0x80 != 0 {
+                break;
+            }
+            if offset >= audio.end {
+                return Err(Error::Malformed);
+            }
+        }
+
+        let info = info.ok_or(Error::Malformed)?;
+        if usize::from(info.channels) > MAX_CHANNELS
+            || info.sample_rate > MAX_SAMPLE_RATE
+            || usize::from(info.max_block_size) > MAX_BLOCK_SIZE
+            || u32::from(info.bits_per_sample) > MAX_BITS_PER_SAMPLE
+        {
+            return Err(Error::Unsupported);
+        }
+        if info.sample_rate == 0 {
+            return Err(Error::Malformed);
+        }
+
+        source.seek_from_start(offset)?;
+        Ok(Self {
+            info,
+            seek_table,
+            audio_start: offset,
+            buffer: BitBuffer::new(audio.end),
+            block: BLOCK.share(),
+            frame_offset: offset,
+            block_len: 0,
+            block_pos: 0,
+            sample_rate: info.sample_rate,
+            channels: info.channels.into(),
+            bits_per_sample: info.bits_per_sample.into(),
+            seek_target: None,
+        })
+    }
+
+    pub fn info(&self) -> &StreamInfo {
+        &self.info
+    }
+
+    pub fn seek_table(&self) -> &[SeekPoint] {
+        &self.seek_table
+    }
+
+    /// Read up to [`MAX_SEEK_POINTS`] evenly spread points of a seek table of `len` bytes
+    fn read_seek_table<S: Source>(
+        source: &mut S,
+        len: u32,
+    ) -> Result<Vec<SeekPoint, MAX_SEEK_POINTS>, Error<S::Error>> {
+        let points = len as usize / 18;
+        let stride = points.div_ceil(MAX_SEEK_POINTS).max(1);
+
+        let mut table = Vec::new();
+        let mut point = [0u8; 18];
+        for i in 0..points {
+            if source.read_full(&mut point)? < point.len() {
+                return Err(Error::Malformed);
+            }
+            let sample = BigEndian::read_u64(&point[0..]);
+            // Placeholder points have a sample number of all ones
+            if i % stride != 0 || sample == u64::MAX {
+                continue;
+            }
+            if let Ok(offset) = u32::try_from(BigEndian::read_u64(&point[8..])) {
+                let _ = table.push(SeekPoint { sample, offset });
+            }
+        }
+        Ok(table)
+    }
+
+    /// Decode the next frame into `block`, returns false at the end of the stream
+    fn read_frame<S: Source>(
+        &mut self,
+        source: &mut S,
+        block: &mut Block,
+    ) -> Result<bool, Error<S::Error>> {
+        let offset = self.buffer.offset(source);
+        if offset >= self.buffer.end {
+            return Ok(false);
+        }
+        let mut reader = BitReader {
+            buffer: &mut self.buffer,
+            source,
+        };
+
+        let header = FrameHeader::read(&mut reader, &self.info)?;
+        let channels = header.channels.count();
+        let len = header.block_size;
+        if channels > MAX_CHANNELS
+            || len > MAX_BLOCK_SIZE
+            || header.bits_per_sample > MAX_BITS_PER_SAMPLE
+        {
+            return Err(Error::Unsupported);
+        }
+
+        for (channel, samples) in block.iter_mut().take(channels).enumerate() {
+            let bits = header.bits_per_sample + u32::from(header.channels.is_side(channel));
+            read_subframe(&mut reader, &mut samples[..len], bits)?;
+        }
+
+        reader.align();
+        let crc = reader.buffer.crc16;
+        if reader.read(16)? != u32::from(crc) {
+            return Err(Error::Malformed);
+        }
+        decorrelate(header.channels, block, len);
+
+        self.frame_offset = offset;
+        self.block_len = len;
+        self.block_pos = 0;
+        self.sample_rate = header.sample_rate;
+        self.channels = channels;
+        self.bits_per_sample = header.bits_per_sample;
+
+        if let Some(target) = self.seek_target {
+            match target.saturating_sub(header.first_sample) {
+                skip if skip < len as u64 => {
+                    self.block_pos = skip as usize;
+                    self.seek_target = None;
+                }
+                _ => self.block_pos = len,
+            }
+        }
+        Ok(true)
+    }
+
+    /// Decode the current frame again after another decoder used the block, keeping the position
+    /// in it
+    fn restore_frame<S: Source>(
+        &mut self,
+        source: &mut S,
+        block: &mut Block,
+    ) -> Result<(), Error<S::Error>> {
+        let (block_len, block_pos) = (self.block_len, self.block_pos);
+        let next = self.buffer.offset(source);
+        source.seek_from_start(self.frame_offset)?;
+        self.buffer.reset();
+        if !self.read_frame(source, block)? || self.block_len != block_len {
+            return Err(Error::Malformed);
+        }
+        self.block_pos = block_pos;
+        debug_assert_eq!(self.buffer.offset(source), next);
+        Ok(())
+    }
+
+    /// Find the first frame header in `from..to`, returns its offset and first sample
+    fn sync<S: Source>(
+        &mut self,
+        source: &mut S,
+        from: u32,
+        to: u32,
+    ) -> Result<Option<(u32, u64)>, Error<S::Error>> {
+        let to = to.min(from.saturating_add(SYNC_LIMIT));
+        let mut buf = [0u8; 256];
+        let mut offset = from;
+
+        while offset < to {
+            source.seek_from_start(offset)?;
+            let read = source.read_full(&mut buf[..((to - offset) as usize + 1).min(256)])?;
+            if read < 2 {
+                break;
+            }
+
+            for i in 0..read - 1 {
+                if buf[i] != 0xFF || buf[i + 1] & 0xFE != 0xF8 {
+                    continue;
+                }
+                let candidate = offset + i as u32;
+                source.seek_from_start(candidate)?;
+                self.buffer.reset();
+                let mut reader = BitReader {
+                    buffer: &mut self.buffer,
+                    source,
+                };
+                match FrameHeader::read(&mut reader, &self.info) {
+                    Ok(header) if header.is_consistent(&self.info) => {
+                        return Ok(Some((candidate, header.first_sample)))
+                    }
+                    Err(Error::Read(err)) => return Err(Error::Read(err)),
+                    _ => {}
+                }
+            }
+            offset += read as u32 - 1;
+        }
+        Ok(None)
+    }
+}
+
+impl AudioDecoder for FlacDecoder {
+    fn decode<S: Source>(
+        &mut self,
+        source: &mut S,
+        pcm: &mut [f32],
+    ) -> Result<Option<PcmInfo>, Error<S::Error>> {
+        let mut block = self.block.lock();
+        if !block.kept() && self.block_pos < self.block_len {
+            self.restore_frame(source, &mut block)?;
+        }
+        while self.block_pos == self.block_len {
+            if !self.read_frame(source, &mut block)? {
+                return Ok(None);
+            }
+        }
+
+        let channels = self.channels;
+        let frames = (self.block_len - self.block_pos).min(pcm.len().min(MAX_SAMPLES) / channels);
+        let scale = 1. / (1u32 << (self.bits_per_sample - 1)) as f32;
+        for (frame, out) in pcm.chunks_exact_mut(channels).take(frames).enumerate() {
+            for (sample, block) in out.iter_mut().zip(block.iter()) {
+                *sample = block[self.block_pos + frame] as f32 * scale;
+            }
+        }
+        self.block_pos += frames;
+
+        Ok(Some(PcmInfo {
+            frames,
+            channels,
+            sample_rate: self.sample_rate,
+        }))
+    }
+
+    /// Starts from the closest seek point and bisects on frame headers from there, then decodes
+    /// up to the exact sample.
+    fn seek<S: Source>(&mut self, source: &mut S, position: u64) -> Result<u64, Error<S::Error>> {
+        let total = self.info.total_samples;
+        let target = match total {
+            0 => position,
+            total => position.min(total),
+        };
+
+        // Known frames around the target as (offset, first sample)
+        let start = self.audio_start;
+        let mut low = self
+            .seek_table
+            .iter()
+            .rev()
+            .find(|point| point.sample <= target)
+            .map_or((start, 0), |point| (start + point.offset, point.sample));
+        let mut high = self
+            .seek_table
+            .iter()
+            .find(|point| point.sample > target)
+            .map_or((self.buffer.end, total), |point| {
+                (start + point.offset, point.sample)
+            });
+
+        let close = u64::from(self.info.max_block_size);
+        for _ in 0..BISECT_STEPS {
+            if high.1 <= low.1 || target - low.1 < close || high.0 <= low.0 {
+                break;
+            }
+            let guess =
+                low.0 + ((target - low.1) * u64::from(high.0 - low.0) / (high.1 - low.1)) as u32;
+            match self.sync(source, guess.max(low.0 + 1), high.0)? {
+                Some((offset, sample)) if sample <= target && sample > low.1 => {
+                    low = (offset, sample)
+                }
+                Some((offset, sample)) if sample > target && offset < high.0 => {
+                    high = (offset, sample)
+                }
+                // No frame starts between the guess and the upper bound
+                _ => high.0 = guess,
+            }
+        }
+
+        source.seek_from_start(low.0)?;
+        self.buffer.reset();
+        self.block_len = 0;
+        self.block_pos = 0;
+        self.seek_target = Some(target);
+        Ok(target)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn length(&self) -> Option<u64> {
+        match self.info.total_samples {
+            0 => None,
+            total => Some(total),
+        }
+    }
+}
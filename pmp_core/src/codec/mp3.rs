@@ -0,0 +1,745 @@
+use core::ops::Range;
+
+use byteorder::{BigEndian, ByteOrder};
+use heapless::Vec;
+
+use crate::fs::Source;
+
+use super::{AudioDecoder, Error, PcmInfo};
+
+/// Samples the decoder itself delays the output by, on top of the encoder delay
+pub const DECODER_DELAY: u32 = 529;
+
+/// Most VBRI table of contents entries kept
+const VBRI_ENTRIES: usize = 128;
+
+/// Longest Layer III frame, 320 kbit/s at 32 kHz with padding
+const MAX_FRAME_LEN: usize = 1441;
+
+/// Bytes of the stream buffered for the decoder, which only decodes a frame it holds whole and
+/// checks the header of the frame after it
+const MP3_BUF: usize = 2 * MAX_FRAME_LEN + 4;
+
+/// Bytes searched for the next frame before giving up
+const RESYNC_LIMIT: u32 = 16 * 1024;
+
+/// Frames decoded and discarded ahead of a seek target, enough to fill the largest bit reservoir
+/// from the smallest MPEG 1 frames
+const SEEK_PREROLL_FRAMES: u32 = 10;
+
+/// Bytes of frame headers read at a time when scanning
+const SCAN_CHUNK: usize = 1024;
+
+/// Frames between the positions remembered when scanning, about 13 s at 44.1 kHz
+pub const CHECKPOINT_INTERVAL: u32 = 512;
+
+/// Most positions remembered when scanning, covering the first 14 minutes at 44.1 kHz
+const CHECKPOINTS: usize = 64;
+
+const BITRATES_V1: [u16; 15] = [
+    0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+];
+const BITRATES_V2: [u16; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Mpeg1,
+    Mpeg2,
+    Mpeg25,
+}
+
+/// Header of a single MPEG 1/2/2.5 Layer III frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub version: Version,
+    /// Bitrate in kbit/s
+    pub bitrate: u16,
+    pub sample_rate: u32,
+    pub padding: bool,
+    pub channels: u8,
+}
+
+impl FrameHeader {
+    /// Parse the 4 byte frame header at the start of `bytes`
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let header = BigEndian::read_u32(bytes.get(..4)?);
+        if header >> 21 != 0x7FF {
+            return None;
+        }
+
+        let version = match (header >> 19) & 3 {
+            0 => Version::Mpeg25,
+            2 => Version::Mpeg2,
+            3 => Version::Mpeg1,
+            _ => return None,
+        };
+        // Only layer III is supported by the decoder
+        if (header >> 17) & 3 != 1 {
+            return None;
+        }
+
+        let bitrates = match version {
+            Version::Mpeg1 => &BITRATES_V1,
+            _ => &BITRATES_V2,
+        };
+        // Free format bitrates are not supported
+        let bitrate = match bitrates.get(((header >> 12) & 0xF) as usize) {
+            Some(0) | None => return None,
+            Some(bitrate) => *bitrate,
+        };
+
+        let sample_rate = *SAMPLE_RATES.get(((header >> 10) & 3) as usize)?
+            >> match version {
+                Version::Mpeg1 => 0,
+                Version::Mpeg2 => 1,
+                Version::Mpeg25 => 2,
+            };
+
+        Some(Self {
+            version,
+            bitrate,
+            sample_rate,
+            padding: (header >> 9) & 1 == 1,
+            channels: if (header >> 6) & 3 == 3 { 1 } else { 2 },
+        })
+    }
+
+    /// Samples per channel in the frame
+    pub fn samples_per_frame(&self) -> u32 {
+        match self.version {
+            Version::Mpeg1 => 1152,
+            _ => 576,
+        }
+    }
+
+    /// Length of the whole frame in bytes including the header
+    pub fn frame_len(&self) -> u32 {
+        let coefficient = self.samples_per_frame() / 8;
+        coefficient * u32::from(self.bitrate) * 1000 / self.sample_rate + u32::from(self.padding)
+    }
+
+    /// Offset of the Xing/Info header from the start of the frame, directly after the side
+    /// information
+    fn xing_offset(&self) -> usize {
+        4 + match (self.version, self.channels) {
+            (Version::Mpeg1, 1) => 17,
+            (Version::Mpeg1, _) => 32,
+            (_, 1) => 9,
+            (_, _) => 17,
+        }
+    }
+
+    /// True if `other` could be the next frame of the same stream
+    fn is_compatible(&self, other: &Self) -> bool {
+        self.version == other.version && self.sample_rate == other.sample_rate
+    }
+}
+
+/// Encoder delay and padding from the LAME extension of the Xing/Info header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LameTag {
+    pub delay: u16,
+    pub padding: u16,
+}
+
+/// Xing (VBR) or Info (CBR) header stored in the first frame of a stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XingHeader {
+    /// Number of audio frames, excluding the frame holding this header
+    pub frames: Option<u32>,
+    /// Number of bytes in the stream, including the frame holding this header
+    pub bytes: Option<u32>,
+    pub toc: Option<[u8; 100]>,
+    pub lame: Option<LameTag>,
+}
+
+impl XingHeader {
+    /// Parse the Xing/Info header inside of `frame`
+    pub fn parse(header: &FrameHeader, frame: &[u8]) -> Option<Self> {
+        let mut data = frame.get(header.xing_offset()..)?;
+        if !matches!(data.get(..4)?, b"Xing" | b"Info") {
+            return None;
+        }
+        let flags = BigEndian::read_u32(data.get(4..8)?);
+        data = &data[8..];
+
+        let mut field = |flag: u32, len: usize| -> Option<Option<&[u8]>> {
+            if flags & flag == 0 {
+                return Some(None);
+            }
+            let (value, rest) = data.split_at_checked(len)?;
+            data = rest;
+            Some(Some(value))
+        };
+
+        let frames = field(0x1, 4)?.map(BigEndian::read_u32);
+        let bytes = field(0x2, 4)?.map(BigEndian::read_u32);
+        let toc =
+            field(0x4, 100)?.map(|toc| <[u8; 100]>::try_from(toc).expect("Length is checked"));
+        let _quality = field(0x8, 4)?;
+
+        Some(Self {
+            frames,
+            bytes,
+            toc,
+            lame: Self::parse_lame(data),
+        })
+    }
+
+    fn parse_lame(data: &[u8]) -> Option<LameTag> {
+        // Written by LAME and by the LAME compatible ffmpeg encoders
+        if !matches!(data.get(..4)?, b"LAME" | b"Lavf" | b"Lavc") {
+            return None;
+        }
+        let delay_padding = data.get(21..24)?;
+        Some(LameTag {
+            delay: (u16::from(delay_padding[0]) << 4) | (u16::from(delay_padding[1]) >> 4),
+            padding: (u16::from(delay_padding[1] & 0xF) << 8) | u16::from(delay_padding[2]),
+        })
+    }
+}
+
+/// VBRI header written by the Fraunhofer encoder in place of a Xing header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VbriHeader {
+    pub bytes: u32,
+    pub frames: u32,
+    pub frames_per_entry: u32,
+    /// Offset of every `frames_per_entry`th frame from the first audio frame, empty if the table
+    /// did not fit
+    pub toc: Vec<u32, VBRI_ENTRIES>,
+}
+
+impl VbriHeader {
+    /// Parse the VBRI header inside of `frame`, which always follows 32 bytes of side information
+    pub fn parse(frame: &[u8]) -> Option<Self> {
+        let data = frame.get(36..)?;
+        if data.get(..4)? != b"VBRI" {
+            return None;
+        }
+        let header = data.get(..26)?;
+        let entries = usize::from(BigEndian::read_u16(&header[18..20]));
+        let scale = u32::from(BigEndian::read_u16(&header[20..22]));
+        let entry_size = usize::from(BigEndian::read_u16(&header[22..24]));
+
+        let mut toc = Vec::new();
+        let table = data.get(26..26 + entries * entry_size);
+        if let Some(table) = table.filter(|_| (1..=4).contains(&entry_size)) {
+            let mut offset = 0;
+            for entry in table.chunks_exact(entry_size) {
+                if toc.push(offset).is_err() {
+                    break;
+                }
+                offset += BigEndian::read_uint(entry, entry_size) as u32 * scale;
+            }
+        }
+
+        Some(Self {
+            bytes: BigEndian::read_u32(&header[10..14]),
+            frames: BigEndian::read_u32(&header[14..18]),
+            frames_per_entry: u32::from(BigEndian::read_u16(&header[24..26])),
+            toc,
+        })
+    }
+}
+
+/// Frame to restart decoding from after a seek
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeekPoint {
+    /// File offset at or just before the frame
+    pub offset: u32,
+    /// Index of the frame, counted from the first audio frame
+    pub frame: u32,
+}
+
+/// Positions of every [`CHECKPOINT_INTERVAL`]th frame found by [`scan`] or by decoding, so that
+/// seeking scans from the start only once and back to frames already played exactly
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Checkpoints {
+    points: Vec<SeekPoint, CHECKPOINTS>,
+}
+
+impl Checkpoints {
+    /// Closest remembered frame at or before `frame`
+    pub fn before(&self, frame: u32) -> Option<SeekPoint> {
+        self.points
+            .iter()
+            .rev()
+            .find(|point| point.frame <= frame)
+            .copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Remember `point` if it is the next checkpoint, which keeps them evenly spaced from the
+    /// start whichever checkpoint a scan started from
+    fn record(&mut self, point: SeekPoint) {
+        let next = (self.points.len() as u32 + 1) * CHECKPOINT_INTERVAL;
+        if point.frame == next {
+            let _ = self.points.push(point);
+        }
+    }
+}
+
+/// Samples to drop so that only the encoded audio is played back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trim {
+    /// Samples per channel to drop at the start of the decoded output
+    pub skip: u32,
+    /// Samples per channel of actual audio after the skipped samples
+    pub length: u64,
+}
+
+/// Layout of an MP3 stream, gathered from the start of the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamInfo {
+    /// Header of the first frame
+    pub header: FrameHeader,
+    /// File offset of the first frame
+    pub first_frame: u32,
+    /// File offset of the first frame holding audio, after any Xing/Info frame
+    pub audio_start: u32,
+    pub xing: Option<XingHeader>,
+    pub vbri: Option<VbriHeader>,
+}
+
+impl StreamInfo {
+    /// Find the first frame in `buf`, which was read from the file at `offset`
+    pub fn parse(buf: &[u8], offset: u32) -> Option<Self> {
+        let (position, header) = find_frame(buf)?;
+        let frame = &buf[position..];
+        let first_frame = offset + position as u32;
+        let xing = XingHeader::parse(&header, frame);
+        let vbri = VbriHeader::parse(frame);
+
+        Some(Self {
+            header,
+            first_frame,
+            audio_start: match xing.is_some() || vbri.is_some() {
+                true => first_frame + header.frame_len(),
+                false => first_frame,
+            },
+            xing,
+            vbri,
+        })
+    }
+
+    /// Use the Xing or VBRI table of contents to find where audio frame `frame` starts
+    ///
+    /// Xing tables only have a resolution of 1/256 of the stream, so the offset needs to be synced
+    /// to the next frame header and the frame returned is only an estimate from the offset.
+    pub fn lookup(&self, frame: u32) -> Option<SeekPoint> {
+        if let Some(vbri) = self.vbri.as_ref() {
+            let entry = frame / vbri.frames_per_entry.max(1);
+            let offset = vbri.toc.get(entry as usize)?;
+            return Some(SeekPoint {
+                offset: self.audio_start + offset,
+                frame: entry * vbri.frames_per_entry,
+            });
+        }
+
+        let xing = self.xing.as_ref()?;
+        let (toc, frames, bytes) = (xing.toc.as_ref()?, xing.frames?, xing.bytes?);
+        let percent = (frame as f32 / frames.max(1) as f32 * 100.).clamp(0., 99.99);
+        let index = percent as usize;
+        let lower = f32::from(toc[index]);
+        let upper = toc.get(index + 1).map_or(256., |upper| f32::from(*upper));
+        let position = lower + (upper - lower) * (percent - index as f32);
+
+        let offset =
+            (self.first_frame + (position / 256. * bytes as f32) as u32).max(self.audio_start);
+        Some(SeekPoint {
+            offset,
+            frame: self.toc_frame(offset)?,
+        })
+    }
+
+    /// Estimate the frame at `offset` from the Xing table of contents, the inverse of
+    /// [`StreamInfo::lookup`]
+    pub fn toc_frame(&self, offset: u32) -> Option<u32> {
+        let xing = self.xing.as_ref()?;
+        let (toc, frames, bytes) = (xing.toc.as_ref()?, xing.frames?, xing.bytes?);
+        let position =
+            (offset.saturating_sub(self.first_frame) as f32 / bytes.max(1) as f32 * 256.).min(256.);
+        let index = toc
+            .iter()
+            .rposition(|&entry| f32::from(entry) <= position)?;
+        let lower = f32::from(toc[index]);
+        let upper = toc.get(index + 1).map_or(256., |upper| f32::from(*upper));
+        let percent = match upper > lower {
+            true => index as f32 + (position - lower) / (upper - lower),
+            false => index as f32,
+        };
+        Some(((percent / 100. * frames as f32) as u32).min(frames))
+    }
+
+    /// Samples to trim for gapless playback, if the encoder recorded its delay and padding
+    pub fn trim(&self) -> Option<Trim> {
+        let xing = self.xing.as_ref()?;
+        let lame = xing.lame?;
+        let total = u64::from(xing.frames?) * u64::from(self.header.samples_per_frame());
+
+        Some(Trim {
+            skip: u32::from(lame.delay) + DECODER_DELAY,
+            length: total.saturating_sub(u64::from(lame.delay) + u64::from(lame.padding)),
+        })
+    }
+
+    /// Samples per channel in the stream, ending at `audio_end` in the file
+    ///
+    /// Taken from the frame count of the Xing or VBRI header, otherwise estimated from the size
+    /// of the audio assuming a constant bitrate.
+    pub fn samples(&self, audio_end: u32) -> u64 {
+        let samples_per_frame = u64::from(self.header.samples_per_frame());
+        if let Some(trim) = self.trim() {
+            return trim.length;
+        }
+        if let Some(frames) = self.xing.as_ref().and_then(|xing| xing.frames) {
+            return u64::from(frames) * samples_per_frame;
+        }
+        if let Some(vbri) = self.vbri.as_ref() {
+            return u64::from(vbri.frames) * samples_per_frame;
+        }
+
+        let bytes = u64::from(audio_end.saturating_sub(self.audio_start));
+        bytes * 8 * u64::from(self.header.sample_rate) / (u64::from(self.header.bitrate) * 1000)
+    }
+}
+
+/// Find the first frame header in `buf` which is followed by another frame of the same stream,
+/// or by the end of `buf`
+pub fn find_frame(buf: &[u8]) -> Option<(usize, FrameHeader)> {
+    (0..buf.len().saturating_sub(3)).find_map(|position| {
+        let header = FrameHeader::parse(&buf[position..])?;
+        let next = position + header.frame_len() as usize;
+        match buf.get(next..next + 4) {
+            Some(bytes) => FrameHeader::parse(bytes)
+                .filter(|next| header.is_compatible(next))
+                .map(|_| (position, header)),
+            None => Some((position, header)),
+        }
+    })
+}
+
+/// Walk frame headers from the frame at `offset` with index `frame` until reaching frame `target`,
+/// adding the frames passed to `checkpoints`
+///
+/// Returns the offset and index of the frame reached, which is before `target` if the stream
+/// ended first.
+pub fn scan<S: Source>(
+    source: &mut S,
+    mut offset: u32,
+    mut frame: u32,
+    target: u32,
+    checkpoints: &mut Checkpoints,
+) -> Result<(u32, u32), S::Error> {
+    let mut chunk = [0u8; SCAN_CHUNK];
+    // Offset of `chunk` in the source, and the bytes read into it
+    let (mut start, mut len) = (offset, 0);
+    while frame < target {
+        // Only seek and read again once the next header is outside of the chunk
+        if offset + 4 > start + len as u32 {
+            source.seek_from_start(offset)?;
+            start = offset;
+            len = source.read_full(&mut chunk)?;
+            if len < 4 {
+                break;
+            }
+        }
+        offset = match FrameHeader::parse(&chunk[(offset - start) as usize..len]) {
+            Some(header) => {
+                frame += 1;
+                let next = offset + header.frame_len();
+                checkpoints.record(SeekPoint {
+                    offset: next,
+                    frame,
+                });
+                next
+            }
+            // Skip junk between frames
+            None => match resync(source, offset + 1)? {
+                Some(offset) => offset,
+                None => break,
+            },
+        };
+    }
+    Ok((offset, frame))
+}
+
+/// Find the first frame starting at or after `offset`
+pub fn resync<S: Source>(source: &mut S, offset: u32) -> Result<Option<u32>, S::Error> {
+    let mut buf = [0u8; 512];
+    let mut position = offset;
+    while position < offset + RESYNC_LIMIT {
+        source.seek_from_start(position)?;
+        let read = source.read_full(&mut buf)?;
+        if read < 4 {
+            break;
+        }
+        if let Some((found, _)) = find_frame(&buf[..read]) {
+            return Ok(Some(position + found as u32));
+        }
+        position += (read - 3) as u32;
+    }
+    Ok(None)
+}
+
+/// MPEG Layer III decoder
+pub struct Mp3Decoder {
+    decoder: nanomp3::Decoder,
+    stream: StreamInfo,
+    /// End of the audio data, before any trailing tags
+    audio_end: u32,
+    sample_rate: u32,
+    channels: usize,
+    /// Samples per channel still to drop from the start of the track
+    skip: u32,
+    /// Index of the next frame to decode, counted from the first audio frame
+    frame: u32,
+    /// Whether `frame` is exact rather than estimated from a Xing table of contents
+    exact: bool,
+    /// Position in the decoder output a seek is heading for, reached by dropping whole frames
+    seek_to: Option<u64>,
+    /// Whether the last frame decoded with its whole bit reservoir, so the next one also overlaps
+    /// correctly with it
+    primed: bool,
+    /// Frames passed by earlier seeks and by decoding
+    checkpoints: Checkpoints,
+    /// Samples per channel left to play, if known
+    remaining: Option<u64>,
+    mp3_buf: [u8; MP3_BUF],
+    mp3_pos: usize,
+    mp3_len: usize,
+}
+
+impl Mp3Decoder {
+    /// Open the stream in `audio`, syncing to its first frame
+    pub fn open<S: Source>(source: &mut S, audio: Range<u32>) -> Result<Self, Error<S::Error>> {
+        let mut buf = [0u8; 1024];
+        source.seek_from_start(audio.start)?;
+        let read = source.read_full(&mut buf)?.min(audio.len());
+        let stream = StreamInfo::parse(&buf[..read], audio.start).ok_or(Error::Unsupported)?;
+
+        // Start decoding after the Xing/Info frame, which only holds silence
+        source.seek_from_start(stream.audio_start)?;
+        let trim = stream.trim();
+
+        Ok(Self {
+            decoder: nanomp3::Decoder::new(),
+            audio_end: audio.end,
+            sample_rate: stream.header.sample_rate,
+            channels: stream.header.channels.into(),
+            skip: trim.map_or(0, |trim| trim.skip),
+            frame: 0,
+            exact: true,
+            seek_to: None,
+            primed: true,
+            checkpoints: Checkpoints::default(),
+            remaining: trim.map(|trim| trim.length),
+            stream,
+            mp3_buf: [0u8; MP3_BUF],
+            mp3_pos: 0,
+            mp3_len: 0,
+        })
+    }
+
+    pub fn stream(&self) -> &StreamInfo {
+        &self.stream
+    }
+
+    pub fn checkpoints(&self) -> &Checkpoints {
+        &self.checkpoints
+    }
+
+    /// Move the bytes not decoded yet to the front of the buffer and read more of the stream
+    /// behind them, returns the number of bytes read
+    fn refill<S: Source>(&mut self, source: &mut S) -> Result<usize, Error<S::Error>> {
+        self.mp3_buf.copy_within(self.mp3_pos..self.mp3_len, 0);
+        self.mp3_len -= self.mp3_pos;
+        self.mp3_pos = 0;
+
+        // Stop before any trailing tags
+        let len =
+            (self.audio_end.saturating_sub(source.offset()) as usize).min(MP3_BUF - self.mp3_len);
+        let read = source.read_full(&mut self.mp3_buf[self.mp3_len..self.mp3_len + len])?;
+        self.mp3_len += read;
+        Ok(read)
+    }
+
+    /// Drop encoder delay and padding from a decoded frame of `frames` samples per channel,
+    /// returns the first sample and number of samples to keep
+    fn trim(&mut self, frames: usize) -> (usize, usize) {
+        let skip = frames.min(self.skip as usize);
+        self.skip -= skip as u32;
+
+        let mut keep = frames - skip;
+        if let Some(remaining) = self.remaining.as_mut() {
+            keep = keep.min(*remaining as usize);
+            *remaining -= keep as u64;
+        }
+        (skip, keep)
+    }
+}
+
+impl AudioDecoder for Mp3Decoder {
+    fn decode<S: Source>(
+        &mut self,
+        source: &mut S,
+        pcm: &mut [f32],
+    ) -> Result<Option<PcmInfo>, Error<S::Error>> {
+        loop {
+            if self.remaining == Some(0) {
+                return Ok(None);
+            }
+
+            // Top up before less than a frame and the next header are left
+            if self.mp3_len - self.mp3_pos < MAX_FRAME_LEN + 4 {
+                self.refill(source)?;
+            }
+            if self.mp3_pos == self.mp3_len {
+                return Ok(None);
+            }
+
+            let offset = source.offset() - (self.mp3_len - self.mp3_pos) as u32;
+            let mp3 = &self.mp3_buf[self.mp3_pos..self.mp3_len];
+            let (consumed, info) = self.decoder.decode(mp3, pcm);
+            // Frames without the start of their bit reservoir are consumed without any output
+            let whole = FrameHeader::parse(mp3)
+                .is_some_and(|header| header.frame_len() as usize == consumed);
+            let frame = info.is_some() || whole;
+            self.mp3_pos += consumed;
+            // What is left is not a whole frame, and the stream ends before the rest of it
+            if consumed == 0 && info.is_none() && self.refill(source)? == 0 {
+                return Ok(None);
+            }
+            if !frame {
+                continue;
+            }
+            let index = self.frame;
+            self.frame += 1;
+            // Seeking back to frames already played scans from here
+            if whole && self.exact {
+                self.checkpoints.record(SeekPoint {
+                    offset,
+                    frame: index,
+                });
+            }
+
+            let Some(info) = info else {
+                self.primed = false;
+                continue;
+            };
+            if let Some(to) = self.seek_to {
+                let primed = core::mem::replace(&mut self.primed, true);
+                let start = u64::from(index) * u64::from(self.stream.header.samples_per_frame());
+                // Drop the first frame decoded after the seek as it is missing the overlap with
+                // the one before it, then whole frames up to the one holding the target
+                if !primed || start + info.samples_produced as u64 <= to {
+                    continue;
+                }
+                self.seek_to = None;
+                self.skip = to.saturating_sub(start) as u32;
+                // The reservoir took longer to fill than the preroll and the target was passed
+                if let Some(remaining) = self.remaining.as_mut() {
+                    *remaining = remaining.saturating_sub(start.saturating_sub(to));
+                }
+            }
+
+            let channels = usize::from(info.channels.num());
+            let (start, frames) = self.trim(info.samples_produced);
+            if frames == 0 {
+                continue;
+            }
+            pcm.copy_within(start * channels..(start + frames) * channels, 0);
+
+            self.sample_rate = info.sample_rate;
+            self.channels = channels;
+            return Ok(Some(PcmInfo {
+                frames,
+                channels,
+                sample_rate: self.sample_rate,
+            }));
+        }
+    }
+
+    /// Walks the frame headers from the closest frame passed before, which lands on the exact
+    /// sample. Seeking further than [`CHECKPOINT_INTERVAL`] frames from it uses the Xing/VBRI
+    /// table of contents when present instead, which is only close with a Xing table.
+    fn seek<S: Source>(&mut self, source: &mut S, position: u64) -> Result<u64, Error<S::Error>> {
+        let stream = &self.stream;
+        let samples_per_frame = u64::from(stream.header.samples_per_frame());
+        let trim = stream.trim();
+
+        // Position in the decoder output, which includes the delay
+        let target = match trim {
+            Some(trim) => position.min(trim.length),
+            None => position,
+        };
+        let decoded = target + u64::from(trim.map_or(0, |trim| trim.skip));
+
+        let start = ((decoded / samples_per_frame) as u32).saturating_sub(SEEK_PREROLL_FRAMES);
+        let from = self.checkpoints.before(start).unwrap_or(SeekPoint {
+            offset: stream.audio_start,
+            frame: 0,
+        });
+        // Scanning a short way from a known frame is exact, unlike the table of contents
+        let point = match start - from.frame < CHECKPOINT_INTERVAL {
+            true => None,
+            false => stream.lookup(start),
+        };
+        let (point, exact) = match point {
+            Some(point) => {
+                let offset = resync(source, point.offset)?.unwrap_or(point.offset);
+                let estimate = stream.toc_frame(offset);
+                let frame = estimate.unwrap_or(point.frame);
+                (
+                    SeekPoint { offset, frame },
+                    estimate.is_none() && offset == point.offset,
+                )
+            }
+            None => {
+                let (offset, frame) = scan(
+                    source,
+                    from.offset,
+                    from.frame,
+                    start,
+                    &mut self.checkpoints,
+                )?;
+                (SeekPoint { offset, frame }, true)
+            }
+        };
+
+        source.seek_from_start(point.offset)?;
+        self.decoder = nanomp3::Decoder::new();
+        self.mp3_pos = 0;
+        self.mp3_len = 0;
+
+        // Decoding picks up the bit reservoir over the frames before the target
+        self.frame = point.frame;
+        self.exact = exact;
+        self.seek_to = Some(decoded);
+        self.primed = point.frame == 0;
+        self.skip = 0;
+        self.remaining = trim.map(|trim| trim.length - target);
+        Ok(target)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn length(&self) -> Option<u64> {
+        Some(self.stream.samples(self.audio_end))
+    }
+}
@@ -0,0 +1,171 @@
+use core::ops::Range;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::fs::Source;
+
+use super::{AudioDecoder, Error, Format, PcmInfo, MAX_SAMPLES};
+
+const FORMAT_PCM: u16 = 0x0001;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Largest `block_align` supported, 24-bit stereo
+const MAX_BLOCK_ALIGN: usize = 6;
+
+/// Frames read per call to [`WavDecoder::decode`], one MP3 frame worth
+const FRAMES_PER_BLOCK: usize = 576;
+
+/// Uncompressed pcm in a RIFF/WAVE container
+#[derive(Debug)]
+pub struct WavDecoder {
+    sample_rate: u32,
+    channels: usize,
+    bits_per_sample: u16,
+    /// Bytes per frame of all channels
+    block_align: usize,
+    /// Offset of the first sample
+    data_start: u32,
+    /// Bytes of pcm, never past the end of the audio
+    data_len: u32,
+}
+
+impl WavDecoder {
+    /// Parse the RIFF header and chunks in `audio`, stopping at the `data` chunk
+    pub fn open<S: Source>(source: &mut S, audio: Range<u32>) -> Result<Self, Error<S::Error>> {
+        let mut header = [0u8; 12];
+        source.seek_from_start(audio.start)?;
+        let read = source.read_full(&mut header)?;
+        if Format::detect(&header[..read]) != Some(Format::Wav) {
+            return Err(Error::Unsupported);
+        }
+
+        let mut format = None;
+        let mut offset = audio.start + 12;
+        loop {
+            let mut chunk = [0u8; 8];
+            source.seek_from_start(offset)?;
+            if source.read_full(&mut chunk)? < chunk.len() {
+                return Err(Error::Malformed);
+            }
+            let len = LittleEndian::read_u32(&chunk[4..]);
+            let body = offset + 8;
+
+            match &chunk[..4] {
+                b"fmt " => {
+                    let mut fmt = [0u8; 40];
+                    let read = source.read_full(&mut fmt[..(len as usize).min(40)])?;
+                    format = Some(Self::parse_format(&fmt[..read])?);
+                }
+                b"data" => {
+                    let (sample_rate, channels, bits_per_sample) =
+                        format.ok_or(Error::Malformed)?;
+                    let block_align = channels * usize::from(bits_per_sample / 8);
+                    let data_len = len.min(audio.end.saturating_sub(body));
+
+                    source.seek_from_start(body)?;
+                    return Ok(Self {
+                        sample_rate,
+                        channels,
+                        bits_per_sample,
+                        block_align,
+                        data_start: body,
+                        data_len: data_len - data_len % block_align as u32,
+                    });
+                }
+                _ => {}
+            }
+
+            // Chunks are padded to an even length
+            offset = body.saturating_add(len).saturating_add(len & 1);
+            if offset >= audio.end {
+                return Err(Error::Malformed);
+            }
+        }
+    }
+
+    /// Parse a `fmt ` chunk into sample rate, channels and bits per sample
+    fn parse_format<E>(fmt: &[u8]) -> Result<(u32, usize, u16), Error<E>> {
+        if fmt.len() < 16 {
+            return Err(Error::Malformed);
+        }
+        let tag = LittleEndian::read_u16(&fmt[0..]);
+        let channels = LittleEndian::read_u16(&fmt[2..]);
+        let sample_rate = LittleEndian::read_u32(&fmt[4..]);
+        let bits_per_sample = LittleEndian::read_u16(&fmt[14..]);
+
+        let pcm = match tag {
+            FORMAT_PCM => true,
+            // The sub format GUID starts with the format tag
+            FORMAT_EXTENSIBLE => {
+                fmt.len() >= 26 && LittleEndian::read_u16(&fmt[24..]) == FORMAT_PCM
+            }
+            _ => false,
+        };
+        if !pcm || !matches!(channels, 1 | 2) || !matches!(bits_per_sample, 16 | 24) {
+            return Err(Error::Unsupported);
+        }
+        if sample_rate == 0 {
+            return Err(Error::Malformed);
+        }
+        Ok((sample_rate, channels.into(), bits_per_sample))
+    }
+}
+
+impl AudioDecoder for WavDecoder {
+    fn decode<S: Source>(
+        &mut self,
+        source: &mut S,
+        pcm: &mut [f32],
+    ) -> Result<Option<PcmInfo>, Error<S::Error>> {
+        let mut bytes = [0u8; FRAMES_PER_BLOCK * MAX_BLOCK_ALIGN];
+
+        let left = (self.data_start + self.data_len).saturating_sub(source.offset()) as usize;
+        let frames = FRAMES_PER_BLOCK
+            .min(MAX_SAMPLES / self.channels)
+            .min(pcm.len() / self.channels)
+            .min(left / self.block_align);
+        if frames == 0 {
+            return Ok(None);
+        }
+
+        let read = source.read_full(&mut bytes[..frames * self.block_align])?;
+        let frames = read / self.block_align;
+        if frames == 0 {
+            return Ok(None);
+        }
+
+        let width = usize::from(self.bits_per_sample / 8);
+        let samples = bytes[..frames * self.block_align].chunks_exact(width);
+        for (sample, bytes) in pcm.iter_mut().zip(samples) {
+            *sample = match width {
+                2 => f32::from(LittleEndian::read_i16(bytes)) / 32768.,
+                _ => LittleEndian::read_i24(bytes) as f32 / 8388608.,
+            };
+        }
+
+        Ok(Some(PcmInfo {
+            frames,
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+        }))
+    }
+
+    /// Exact, every frame has the same size
+    fn seek<S: Source>(&mut self, source: &mut S, position: u64) -> Result<u64, Error<S::Error>> {
+        let position = position.min(self.length().unwrap_or(0));
+        source.seek_from_start(self.data_start + position as u32 * self.block_align as u32)?;
+        Ok(position)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn length(&self) -> Option<u64> {
+        Some(u64::from(self.data_len) / self.block_align as u64)
+    }
+}
@@ -0,0 +1,9 @@
+//! Reading tracks from any seekable byte stream
+
+pub use self::{
+    read_ahead::{ReadAhead, ReadRate, ReadStats, SD_BLOCK},
+    source::{SliceSource, Source},
+};
+
+mod read_ahead;
+mod source;
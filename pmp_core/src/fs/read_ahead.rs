@@ -0,0 +1,201 @@
+//! Read-ahead buffering of a [`Source`]
+
+use core::fmt;
+
+use crate::shared::{Share, SharedBuffer};
+
+use super::Source;
+
+/// Size of an SD card block, which reads from the card are aligned to
+pub const SD_BLOCK: usize = 512;
+
+/// Counts of reads through a [`ReadAhead`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReadStats {
+    /// Reads by the user of the buffer, which would each have gone to the source without it
+    pub reads: u64,
+    /// Reads that went through to the source
+    pub source_reads: u64,
+    /// Bytes read from the source
+    pub source_bytes: u64,
+}
+
+impl ReadStats {
+    /// Reads made since `earlier` stats of the same buffer
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            reads: self.reads.wrapping_sub(earlier.reads),
+            source_reads: self.source_reads.wrapping_sub(earlier.source_reads),
+            source_bytes: self.source_bytes.wrapping_sub(earlier.source_bytes),
+        }
+    }
+
+    /// Rate of these reads if they were made over `millis` milliseconds
+    pub fn rate(&self, millis: u64) -> ReadRate {
+        let millis = millis.max(1);
+        ReadRate {
+            unbuffered: self.reads * 1000 / millis,
+            buffered: self.source_reads * 1000 / millis,
+            bytes: self.source_bytes * 1000 / millis,
+        }
+    }
+}
+
+/// Reads per second of the source with and without a [`ReadAhead`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReadRate {
+    /// Reads by the user of the buffer, each of which would go to the source without it
+    pub unbuffered: u64,
+    /// Reads that went through to the source
+    pub buffered: u64,
+    /// Bytes read from the source
+    pub bytes: u64,
+}
+
+impl ReadRate {
+    /// Times fewer reads the source gets with the buffer
+    pub fn reduction(&self) -> u64 {
+        self.unbuffered / self.buffered.max(1)
+    }
+}
+
+/// Formats as `1200 reads/s unbuffered, 38 reads/s buffered (156000 B/s), 31x fewer`
+impl fmt::Display for ReadRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} reads/s unbuffered, {} reads/s buffered ({} B/s), {}x fewer",
+            self.unbuffered,
+            self.buffered,
+            self.bytes,
+            self.reduction()
+        )
+    }
+}
+
+/// [`Source`] reading `N` bytes at a time from the wrapped source, so that the many small reads
+/// of a decoder turn into a few bulk transfers from the card
+///
+/// Refills start on an [`SD_BLOCK`] boundary and `N` must be a multiple of it, so that every
+/// block of the card is read whole. Seeks within the buffered bytes do not touch the source.
+///
+/// The buffer is a static shared with the other open tracks, only one of which is read at a
+/// time. Whoever reads next after another track refilled it starts over with a refill.
+pub struct ReadAhead<S, const N: usize> {
+    source: S,
+    buf: Share<'static, [u8; N]>,
+    /// Offset in the source of the start of `buf`
+    start: u32,
+    pos: usize,
+    len: usize,
+    stats: ReadStats,
+}
+
+impl<S: Source, const N: usize> ReadAhead<S, N> {
+    /// Buffer reads of `source` from its current offset in `buf`
+    pub fn new(source: S, buf: &'static SharedBuffer<[u8; N]>) -> Self {
+        const { assert!(N > 0 && N.is_multiple_of(SD_BLOCK)) };
+        Self {
+            start: source.offset(),
+            source,
+            buf: buf.share(),
+            pos: 0,
+            len: 0,
+            stats: ReadStats::default(),
+        }
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+
+    pub fn stats(&self) -> ReadStats {
+        self.stats
+    }
+
+    /// Bytes buffered ahead of the current offset
+    pub fn buffered(&self) -> usize {
+        self.len.saturating_sub(self.pos)
+    }
+}
+
+/// Read from `source` into `buf` starting at `offset`, counting the read in `stats`
+fn read_source<S: Source>(
+    source: &mut S,
+    stats: &mut ReadStats,
+    offset: u32,
+    buf: &mut [u8],
+) -> Result<usize, S::Error> {
+    if source.offset() != offset {
+        source.seek_from_start(offset)?;
+    }
+    let read = source.read_full(buf)?;
+    stats.source_reads += 1;
+    stats.source_bytes += read as u64;
+    Ok(read)
+}
+
+impl<S: Source, const N: usize> Source for ReadAhead<S, N> {
+    type Error = S::Error;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.stats.reads += 1;
+        let mut buffer = self.buf.lock();
+        if !buffer.kept() {
+            // Another track refilled the buffer since
+            self.start = self.offset();
+            self.pos = 0;
+            self.len = 0;
+        }
+        if self.pos >= self.len {
+            let offset = self.offset();
+            // Reads as large as the buffer would only be copied twice
+            if buf.len() >= N {
+                let read = read_source(&mut self.source, &mut self.stats, offset, buf)?;
+                self.start = offset + read as u32;
+                self.pos = 0;
+                self.len = 0;
+                return Ok(read);
+            }
+
+            let aligned = offset - offset % SD_BLOCK as u32;
+            self.start = aligned;
+            self.pos = (offset - aligned) as usize;
+            // Left empty if the read fails
+            self.len = 0;
+            self.len = read_source(&mut self.source, &mut self.stats, aligned, &mut *buffer)?;
+            if self.pos >= self.len {
+                return Ok(0);
+            }
+        }
+
+        let n = buf.len().min(self.len - self.pos);
+        buf[..n].copy_from_slice(&buffer[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+
+    fn seek_from_start(&mut self, offset: u32) -> Result<(), Self::Error> {
+        match offset.checked_sub(self.start) {
+            Some(pos) if pos as usize <= self.len => self.pos = pos as usize,
+            _ => {
+                self.start = offset;
+                self.pos = 0;
+                self.len = 0;
+            }
+        }
+        Ok(())
+    }
+
+    fn offset(&self) -> u32 {
+        self.start + self.pos as u32
+    }
+
+    fn length(&self) -> u32 {
+        self.source.length()
+    }
+}
@@ -0,0 +1,71 @@
+use core::convert::Infallible;
+
+/// Seekable byte stream, mirroring the file API of embedded_sdmmc so that parsers can also run
+/// on in memory buffers
+pub trait Source {
+    type Error: core::fmt::Debug;
+
+    /// Read into `buf`, returns the number of bytes read which is 0 at the end of the stream
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+
+    fn seek_from_start(&mut self, offset: u32) -> Result<(), Self::Error>;
+
+    /// Current read position
+    fn offset(&self) -> u32;
+
+    /// Total length of the stream
+    fn length(&self) -> u32;
+
+    fn is_eof(&self) -> bool {
+        self.offset() >= self.length()
+    }
+
+    /// Read until `buf` is full or the end of the stream is reached
+    fn read_full(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.read(&mut buf[filled..])? {
+                0 => break,
+                read => filled += read,
+            }
+        }
+        Ok(filled)
+    }
+}
+
+/// In memory [`Source`]
+pub struct SliceSource<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> SliceSource<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+}
+
+impl Source for SliceSource<'_> {
+    type Error = Infallible;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let remaining = &self.data[self.offset..];
+        let n = remaining.len().min(buf.len());
+        buf[..n].copy_from_slice(&remaining[..n]);
+        self.offset += n;
+        Ok(n)
+    }
+
+    fn seek_from_start(&mut self, offset: u32) -> Result<(), Self::Error> {
+        self.offset = (offset as usize).min(self.data.len());
+        Ok(())
+    }
+
+    fn offset(&self) -> u32 {
+        self.offset as u32
+    }
+
+    fn length(&self) -> u32 {
+        self.data.len() as u32
+    }
+}
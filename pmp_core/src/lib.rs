@@ -0,0 +1,10 @@
+//! Decoding and processing of the player that does not touch the hardware
+//!
+//! Nothing here depends on the target, so the tests run on the host with `cargo test`.
+
+#![no_std]
+
+pub mod codec;
+pub mod fs;
+pub mod player;
+pub mod shared;
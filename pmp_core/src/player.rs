@@ -0,0 +1,19 @@
+//! Processing of decoded audio on its way to the output
+
+pub mod bookmarks;
+pub mod channels;
+pub mod circular;
+pub mod crossfeed;
+pub mod dsp;
+pub mod eq;
+pub mod gain;
+pub mod limiter;
+pub mod pcm;
+pub mod position;
+pub mod queue;
+pub mod resampler;
+pub mod ring;
+pub mod rng;
+pub mod stretch;
+pub mod tags;
+pub mod volume;
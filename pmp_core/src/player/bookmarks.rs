@@ -0,0 +1,61 @@
+//! Positions to resume tracks from
+
+use heapless::Vec;
+
+use super::position::PlaybackPosition;
+
+/// Most tracks with a bookmark in the app
+pub const MAX_BOOKMARKS: usize = 16;
+
+/// Position to resume each of the last `N` tracks left part way, keyed by `K`
+///
+/// Saving a bookmark for a new track once full forgets the one saved longest ago.
+#[derive(Debug, Clone)]
+pub struct Bookmarks<K, const N: usize> {
+    /// Oldest first
+    entries: Vec<(K, PlaybackPosition), N>,
+}
+
+impl<K: PartialEq, const N: usize> Bookmarks<K, N> {
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Remember `position` for `key`, replacing its previous bookmark
+    pub fn save(&mut self, key: K, position: PlaybackPosition) {
+        self.remove(&key);
+        if self.entries.is_full() && !self.entries.is_empty() {
+            self.entries.remove(0);
+        }
+        let _ = self.entries.push((key, position));
+    }
+
+    pub fn get(&self, key: &K) -> Option<PlaybackPosition> {
+        self.entries
+            .iter()
+            .find(|(saved, _)| saved == key)
+            .map(|&(_, position)| position)
+    }
+
+    /// Forget the bookmark of `key`, returning it
+    pub fn remove(&mut self, key: &K) -> Option<PlaybackPosition> {
+        let index = self.entries.iter().position(|(saved, _)| saved == key)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<K: PartialEq, const N: usize> Default for Bookmarks<K, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
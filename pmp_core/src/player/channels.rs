@@ -0,0 +1,89 @@
+//! Channel routing of stereo audio
+
+use super::dsp::Processor;
+
+/// How the left and right channels reach the output
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelMode {
+    #[default]
+    Stereo,
+    /// Both channels mixed together, for a single earbud or speaker
+    Mono,
+    /// Left and right swapped
+    Swap,
+}
+
+impl ChannelMode {
+    pub fn cycle(self) -> Self {
+        match self {
+            Self::Stereo => Self::Mono,
+            Self::Mono => Self::Swap,
+            Self::Swap => Self::Stereo,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Stereo => "Stereo",
+            Self::Mono => "Mono",
+            Self::Swap => "Swap",
+        }
+    }
+}
+
+/// Applies the [`ChannelMode`] then the balance to stereo audio
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ChannelMixer {
+    mode: ChannelMode,
+    balance: f32,
+}
+
+impl ChannelMixer {
+    pub fn new(mode: ChannelMode) -> Self {
+        Self { mode, balance: 0. }
+    }
+
+    pub fn mode(&self) -> ChannelMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: ChannelMode) {
+        self.mode = mode;
+    }
+
+    /// Balance from -1 for left only to 1 for right only
+    pub fn balance(&self) -> f32 {
+        self.balance
+    }
+
+    pub fn set_balance(&mut self, balance: f32) {
+        self.balance = balance.clamp(-1., 1.);
+    }
+
+    /// Gains of the left and right channel, the centre keeps both at unity
+    pub fn balance_gains(&self) -> (f32, f32) {
+        ((1. - self.balance).min(1.), (1. + self.balance).min(1.))
+    }
+}
+
+impl Processor for ChannelMixer {
+    fn process(&mut self, pcm: &mut [f32], channels: usize) {
+        // The player sends mono tracks to both sides before the effects
+        if channels != 2 || (self.mode == ChannelMode::Stereo && self.balance == 0.) {
+            return;
+        }
+        let (left_gain, right_gain) = self.balance_gains();
+        for frame in pcm.chunks_exact_mut(2) {
+            let (left, right) = match self.mode {
+                ChannelMode::Stereo => (frame[0], frame[1]),
+                ChannelMode::Mono => {
+                    let mid = (frame[0] + frame[1]) * 0.5;
+                    (mid, mid)
+                }
+                ChannelMode::Swap => (frame[1], frame[0]),
+            };
+            frame[0] = left * left_gain;
+            frame[1] = right * right_gain;
+        }
+    }
+}
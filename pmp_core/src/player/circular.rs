@@ -0,0 +1,51 @@
+//! Bookkeeping of the circular buffer the I2S transfer plays from
+
+use core::ops::Range;
+
+/// Free part of a circular buffer of `len` bytes, which starts at `write_offset` and holds
+/// `available` bytes, split where it wraps round to the start
+///
+/// The second range is empty unless the free part wraps.
+pub fn free_ranges(len: usize, write_offset: usize, available: usize) -> [Range<usize>; 2] {
+    let available = available.min(len);
+    let end = write_offset + available;
+    if end <= len {
+        [write_offset..end, 0..0]
+    } else {
+        [write_offset..len, 0..end - len]
+    }
+}
+
+/// Counts the times the transfer played the whole buffer without a write in between
+///
+/// The transfer reports every attempt to write while it is behind, so consecutive reports
+/// without a successful write in between count as one underrun.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Underruns {
+    count: u64,
+    late: bool,
+}
+
+impl Underruns {
+    /// The transfer is behind the writer
+    pub fn late(&mut self) {
+        if !self.late {
+            self.late = true;
+            self.count += 1;
+        }
+    }
+
+    /// A write went through, ending any underrun
+    pub fn written(&mut self) {
+        self.late = false;
+    }
+
+    /// True while the last write failed because the transfer was behind
+    pub fn is_late(&self) -> bool {
+        self.late
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
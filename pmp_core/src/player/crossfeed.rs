@@ -0,0 +1,168 @@
+//! Headphone crossfeed after Bauer's stereophonic-to-binaural filter (bs2b)
+//!
+//! Each channel is fed to the other through a low pass, while the direct signal gets a high
+//! shelf to make up for the loudness the crossfed bass adds.
+
+use core::f32::consts::PI;
+
+use super::dsp::Processor;
+
+/// Strength of the crossfeed, from the presets of libbs2b
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CrossfeedLevel {
+    #[default]
+    Off,
+    /// Jan Meier's 650 Hz, 9.5 dB
+    Low,
+    /// Chu Moy's 700 Hz, 6 dB
+    Medium,
+    /// The libbs2b default of 700 Hz, 4.5 dB
+    High,
+}
+
+impl CrossfeedLevel {
+    /// Cut frequency in Hz and level of the crossfed signal below the direct one in dB, `None`
+    /// when off
+    pub fn parameters(self) -> Option<(f32, f32)> {
+        match self {
+            Self::Off => None,
+            Self::Low => Some((650., 9.5)),
+            Self::Medium => Some((700., 6.)),
+            Self::High => Some((700., 4.5)),
+        }
+    }
+
+    /// The next stronger level, wrapping around to off
+    pub fn cycle(self) -> Self {
+        match self {
+            Self::Off => Self::Low,
+            Self::Low => Self::Medium,
+            Self::Medium => Self::High,
+            Self::High => Self::Off,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Low => "Low",
+            Self::Medium => "Medium",
+            Self::High => "High",
+        }
+    }
+}
+
+/// First order filter coefficients of both paths
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Coefficients {
+    lo_a0: f32,
+    lo_b1: f32,
+    hi_a0: f32,
+    hi_a1: f32,
+    hi_b1: f32,
+    gain: f32,
+}
+
+impl Coefficients {
+    fn new(cut: f32, feed_db: f32, sample_rate: u32) -> Self {
+        let lo_db = feed_db * -5. / 6. - 3.;
+        let hi_db = feed_db / 6. - 3.;
+        let lo_gain = libm::powf(10., lo_db / 20.);
+        let hi_gain = 1. - libm::powf(10., hi_db / 20.);
+        let hi_cut = cut * libm::powf(2., (lo_db - 20. * libm::log10f(hi_gain)) / 12.);
+
+        let lo = libm::expf(-2. * PI * cut / sample_rate as f32);
+        let hi = libm::expf(-2. * PI * hi_cut / sample_rate as f32);
+        Self {
+            lo_a0: lo_gain * (1. - lo),
+            lo_b1: lo,
+            hi_a0: 1. - hi_gain * (1. - hi),
+            hi_a1: -hi,
+            hi_b1: hi,
+            // Unity gain for bass panned to one side
+            gain: 1. / (1. - hi_gain + lo_gain),
+        }
+    }
+}
+
+/// Filter state of one input channel
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct State {
+    /// Low passed, fed to the other channel
+    lo: f32,
+    /// High shelved, kept in this channel
+    hi: f32,
+    /// Previous input
+    last: f32,
+}
+
+/// Crossfeed for stereo audio, anything else passes through untouched
+pub struct Crossfeed {
+    level: CrossfeedLevel,
+    sample_rate: u32,
+    coefficients: Coefficients,
+    state: [State; 2],
+}
+
+impl Crossfeed {
+    pub fn new(level: CrossfeedLevel, sample_rate: u32) -> Self {
+        let mut crossfeed = Self {
+            level,
+            sample_rate,
+            coefficients: Coefficients::default(),
+            state: [State::default(); 2],
+        };
+        crossfeed.update();
+        crossfeed
+    }
+
+    pub fn level(&self) -> CrossfeedLevel {
+        self.level
+    }
+
+    pub fn set_level(&mut self, level: CrossfeedLevel) {
+        if level != self.level {
+            self.level = level;
+            self.update();
+            self.reset();
+        }
+    }
+
+    fn update(&mut self) {
+        if let Some((cut, feed_db)) = self.level.parameters() {
+            self.coefficients = Coefficients::new(cut, feed_db, self.sample_rate);
+        }
+    }
+}
+
+impl Processor for Crossfeed {
+    fn configure(&mut self, sample_rate: u32, _channels: usize) {
+        if sample_rate != self.sample_rate {
+            self.sample_rate = sample_rate;
+            self.update();
+            self.reset();
+        }
+    }
+
+    fn process(&mut self, pcm: &mut [f32], channels: usize) {
+        // Mono has nothing to cross over
+        if channels != 2 || self.level == CrossfeedLevel::Off {
+            return;
+        }
+        let c = self.coefficients;
+        for frame in pcm.chunks_exact_mut(2) {
+            for (state, &input) in self.state.iter_mut().zip(frame.iter()) {
+                state.lo = c.lo_a0 * input + c.lo_b1 * state.lo;
+                state.hi = c.hi_a0 * input + c.hi_a1 * state.last + c.hi_b1 * state.hi;
+                state.last = input;
+            }
+            let [left, right] = self.state;
+            frame[0] = (left.hi + right.lo) * c.gain;
+            frame[1] = (right.hi + left.lo) * c.gain;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = [State::default(); 2];
+    }
+}
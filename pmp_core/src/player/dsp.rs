@@ -0,0 +1,219 @@
+//! Chain of effects run over decoded PCM before it reaches the [`super::Sink`]
+
+use heapless::Vec;
+
+use super::{channels::ChannelMixer, crossfeed::Crossfeed, eq::Equalizer};
+
+/// Most stages in the chain of the [`super::Player`]
+pub const MAX_EFFECTS: usize = 8;
+
+/// Stage of audio processing working in place on interleaved float PCM
+pub trait Processor {
+    /// Prepare for audio of a new format, called before the first buffer and on every change
+    fn configure(&mut self, _sample_rate: u32, _channels: usize) {}
+
+    fn process(&mut self, pcm: &mut [f32], channels: usize);
+
+    /// Forget past audio, after a seek or when the stage is enabled again
+    fn reset(&mut self) {}
+}
+
+/// Monotonic time source for measuring processing cost
+pub trait Clock {
+    /// Current time in ticks
+    fn now(&self) -> u64;
+}
+
+impl<F: Fn() -> u64> Clock for F {
+    fn now(&self) -> u64 {
+        self()
+    }
+}
+
+/// Time spent processing a number of frames
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Cost {
+    pub ticks: u64,
+    pub frames: u64,
+}
+
+impl Cost {
+    /// Processing time as a fraction of the playing time of the frames, above 1 the chain cannot
+    /// keep up
+    pub fn load(&self, sample_rate: u32, tick_hz: u64) -> f32 {
+        if self.frames == 0 {
+            return 0.;
+        }
+        let seconds = self.ticks as f64 / tick_hz as f64;
+        let played = self.frames as f64 / f64::from(sample_rate);
+        (seconds / played) as f32
+    }
+
+    fn add(&mut self, ticks: u64, frames: u64) {
+        self.ticks += ticks;
+        self.frames += frames;
+    }
+}
+
+/// Every effect the player can run, stored inline as there is no allocator
+#[allow(clippy::large_enum_variant)]
+pub enum Effect {
+    Equalizer(Equalizer),
+    Crossfeed(Crossfeed),
+    Channels(ChannelMixer),
+}
+
+impl Processor for Effect {
+    fn configure(&mut self, sample_rate: u32, channels: usize) {
+        match self {
+            Self::Equalizer(effect) => effect.configure(sample_rate, channels),
+            Self::Crossfeed(effect) => effect.configure(sample_rate, channels),
+            Self::Channels(effect) => effect.configure(sample_rate, channels),
+        }
+    }
+
+    fn process(&mut self, pcm: &mut [f32], channels: usize) {
+        match self {
+            Self::Equalizer(effect) => effect.process(pcm, channels),
+            Self::Crossfeed(effect) => effect.process(pcm, channels),
+            Self::Channels(effect) => effect.process(pcm, channels),
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            Self::Equalizer(effect) => effect.reset(),
+            Self::Crossfeed(effect) => effect.reset(),
+            Self::Channels(effect) => effect.reset(),
+        }
+    }
+}
+
+/// Processor in a [`Chain`] with its bypass switch and cost
+pub struct Stage<P> {
+    processor: P,
+    enabled: bool,
+    cost: Cost,
+}
+
+impl<P: Processor> Stage<P> {
+    pub fn processor(&self) -> &P {
+        &self.processor
+    }
+
+    pub fn processor_mut(&mut self) -> &mut P {
+        &mut self.processor
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Bypass the stage, it starts from a clean state when enabled again
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            self.processor.reset();
+        }
+        self.enabled = enabled;
+    }
+
+    /// Time spent in the stage since the last [`Chain::clear_cost`]
+    pub fn cost(&self) -> Cost {
+        self.cost
+    }
+}
+
+/// Up to `N` processors run in order
+pub struct Chain<P, const N: usize> {
+    stages: Vec<Stage<P>, N>,
+    sample_rate: u32,
+    channels: usize,
+}
+
+impl<P: Processor, const N: usize> Chain<P, N> {
+    pub const fn new() -> Self {
+        Self {
+            stages: Vec::new(),
+            sample_rate: 0,
+            channels: 0,
+        }
+    }
+
+    /// Append an enabled stage, returns its index or the processor if the chain is full
+    pub fn push(&mut self, mut processor: P) -> Result<usize, P> {
+        if self.sample_rate != 0 {
+            processor.configure(self.sample_rate, self.channels);
+        }
+        self.stages
+            .push(Stage {
+                processor,
+                enabled: true,
+                cost: Cost::default(),
+            })
+            .map_err(|stage| stage.processor)?;
+        Ok(self.stages.len() - 1)
+    }
+
+    pub fn stages(&self) -> &[Stage<P>] {
+        &self.stages
+    }
+
+    pub fn stages_mut(&mut self) -> &mut [Stage<P>] {
+        &mut self.stages
+    }
+
+    pub fn stage_mut(&mut self, index: usize) -> Option<&mut Stage<P>> {
+        self.stages.get_mut(index)
+    }
+
+    /// Tell every stage about the format of the following audio if it changed
+    pub fn configure(&mut self, sample_rate: u32, channels: usize) {
+        if sample_rate == self.sample_rate && channels == self.channels {
+            return;
+        }
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+        for stage in &mut self.stages {
+            stage.processor.configure(sample_rate, channels);
+        }
+    }
+
+    /// Run `pcm` through every enabled stage, timing each with `clock`
+    pub fn process(&mut self, pcm: &mut [f32], clock: &impl Clock) {
+        let channels = self.channels.max(1);
+        let frames = (pcm.len() / channels) as u64;
+        for stage in self.stages.iter_mut().filter(|stage| stage.enabled) {
+            let start = clock.now();
+            stage.processor.process(pcm, channels);
+            stage.cost.add(clock.now().wrapping_sub(start), frames);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for stage in &mut self.stages {
+            stage.processor.reset();
+        }
+    }
+
+    /// Combined cost of the enabled stages
+    pub fn cost(&self) -> Cost {
+        let mut total = Cost::default();
+        for stage in self.stages.iter().filter(|stage| stage.enabled) {
+            total.ticks += stage.cost.ticks;
+            total.frames = total.frames.max(stage.cost.frames);
+        }
+        total
+    }
+
+    pub fn clear_cost(&mut self) {
+        for stage in &mut self.stages {
+            stage.cost = Cost::default();
+        }
+    }
+}
+
+impl<P: Processor, const N: usize> Default for Chain<P, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
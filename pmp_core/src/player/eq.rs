@@ -0,0 +1,285 @@
+//! Parametric equalizer built from biquad filters
+//!
+//! Coefficients follow the RBJ audio EQ cookbook.
+
+use core::f32::consts::PI;
+
+use super::{dsp::Processor, resampler::MAX_CHANNELS};
+
+/// Low shelf, peaking bands and high shelf
+pub const BANDS: usize = 7;
+
+/// Highest band frequency relative to the sample rate, the filters fold over at nyquist
+const MAX_FREQUENCY: f32 = 0.45;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterKind {
+    LowShelf,
+    Peak,
+    HighShelf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+    pub kind: FilterKind,
+    /// Centre or corner frequency in Hz
+    pub frequency: f32,
+    pub gain_db: f32,
+    /// Bandwidth of a peak, or steepness of a shelf where 0.707 is the steepest without overshoot
+    pub q: f32,
+}
+
+impl Band {
+    pub const fn new(kind: FilterKind, frequency: f32, q: f32) -> Self {
+        Self {
+            kind,
+            frequency,
+            gain_db: 0.,
+            q,
+        }
+    }
+
+    pub const fn with_gain(mut self, gain_db: f32) -> Self {
+        self.gain_db = gain_db;
+        self
+    }
+}
+
+/// Band layout of [`Preset::FLAT`]
+const DEFAULT_BANDS: [Band; BANDS] = [
+    Band::new(FilterKind::LowShelf, 80., 0.707),
+    Band::new(FilterKind::Peak, 200., 1.),
+    Band::new(FilterKind::Peak, 500., 1.),
+    Band::new(FilterKind::Peak, 1000., 1.),
+    Band::new(FilterKind::Peak, 2500., 1.),
+    Band::new(FilterKind::Peak, 6000., 1.),
+    Band::new(FilterKind::HighShelf, 12000., 0.707),
+];
+
+/// Settings of every band, edit the bands and apply them with [`Equalizer::set_preset`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Preset {
+    pub bands: [Band; BANDS],
+}
+
+impl Preset {
+    pub const FLAT: Self = Self::with_gains([0., 0., 0., 0., 0., 0., 0.]);
+    pub const BASS_BOOST: Self = Self::with_gains([6., 3., 0., 0., 0., 0., 0.]);
+    pub const TREBLE_BOOST: Self = Self::with_gains([0., 0., 0., 0., 1., 3., 6.]);
+    pub const VOCAL: Self = Self::with_gains([-2., -1., 0., 2., 3., 1., 0.]);
+
+    /// Default band layout with `gains` in dB
+    pub const fn with_gains(gains: [f32; BANDS]) -> Self {
+        let mut bands = DEFAULT_BANDS;
+        let mut i = 0;
+        while i < BANDS {
+            bands[i].gain_db = gains[i];
+            i += 1;
+        }
+        Self { bands }
+    }
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Self::FLAT
+    }
+}
+
+/// Normalised biquad coefficients, `a0` is 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coefficients {
+    pub b0: f32,
+    pub b1: f32,
+    pub b2: f32,
+    pub a1: f32,
+    pub a2: f32,
+}
+
+impl Coefficients {
+    /// Passes audio through unchanged
+    pub const IDENTITY: Self = Self {
+        b0: 1.,
+        b1: 0.,
+        b2: 0.,
+        a1: 0.,
+        a2: 0.,
+    };
+
+    pub fn new(band: &Band, sample_rate: u32) -> Self {
+        if band.gain_db == 0. {
+            return Self::IDENTITY;
+        }
+        let frequency = band.frequency.min(sample_rate as f32 * MAX_FREQUENCY);
+        let w0 = 2. * PI * frequency / sample_rate as f32;
+        let (sin, cos) = (libm::sinf(w0), libm::cosf(w0));
+        let a = libm::powf(10., band.gain_db / 40.);
+        let alpha = sin / (2. * band.q);
+
+        let [b0, b1, b2, a0, a1, a2] = match band.kind {
+            FilterKind::Peak => [
+                1. + alpha * a,
+                -2. * cos,
+                1. - alpha * a,
+                1. + alpha / a,
+                -2. * cos,
+                1. - alpha / a,
+            ],
+            FilterKind::LowShelf => {
+                let k = 2. * libm::sqrtf(a) * alpha;
+                [
+                    a * ((a + 1.) - (a - 1.) * cos + k),
+                    2. * a * ((a - 1.) - (a + 1.) * cos),
+                    a * ((a + 1.) - (a - 1.) * cos - k),
+                    (a + 1.) + (a - 1.) * cos + k,
+                    -2. * ((a - 1.) + (a + 1.) * cos),
+                    (a + 1.) + (a - 1.) * cos - k,
+                ]
+            }
+            FilterKind::HighShelf => {
+                let k = 2. * libm::sqrtf(a) * alpha;
+                [
+                    a * ((a + 1.) + (a - 1.) * cos + k),
+                    -2. * a * ((a - 1.) + (a + 1.) * cos),
+                    a * ((a + 1.) + (a - 1.) * cos - k),
+                    (a + 1.) - (a - 1.) * cos + k,
+                    2. * ((a - 1.) - (a + 1.) * cos),
+                    (a + 1.) - (a - 1.) * cos - k,
+                ]
+            }
+        };
+
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// Gain in dB at `frequency`
+    pub fn response(&self, frequency: f32, sample_rate: u32) -> f32 {
+        let w = 2. * PI * frequency / sample_rate as f32;
+        let (cos1, sin1) = (libm::cosf(w), libm::sinf(w));
+        let (cos2, sin2) = (libm::cosf(2. * w), libm::sinf(2. * w));
+        // Evaluate both polynomials at z = e^jw
+        let magnitude = |c0: f32, c1: f32, c2: f32| {
+            let re = c0 + c1 * cos1 + c2 * cos2;
+            let im = c1 * sin1 + c2 * sin2;
+            re * re + im * im
+        };
+        let numerator = magnitude(self.b0, self.b1, self.b2);
+        let denominator = magnitude(1., self.a1, self.a2);
+        10. * libm::log10f(numerator / denominator)
+    }
+}
+
+/// Transposed direct form II filter state of one channel
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct State {
+    z1: f32,
+    z2: f32,
+}
+
+impl State {
+    fn process(&mut self, c: &Coefficients, x: f32) -> f32 {
+        let y = c.b0 * x + self.z1;
+        self.z1 = c.b1 * x - c.a1 * y + self.z2;
+        self.z2 = c.b2 * x - c.a2 * y;
+        y
+    }
+}
+
+/// Equalizer for interleaved float PCM at the rate of the track
+pub struct Equalizer {
+    preset: Preset,
+    sample_rate: u32,
+    coefficients: [Coefficients; BANDS],
+    state: [[State; MAX_CHANNELS]; BANDS],
+}
+
+impl Equalizer {
+    pub fn new(preset: Preset, sample_rate: u32) -> Self {
+        let mut eq = Self {
+            preset,
+            sample_rate,
+            coefficients: [Coefficients::IDENTITY; BANDS],
+            state: [[State::default(); MAX_CHANNELS]; BANDS],
+        };
+        eq.update();
+        eq
+    }
+
+    pub fn preset(&self) -> &Preset {
+        &self.preset
+    }
+
+    pub fn set_preset(&mut self, preset: Preset) {
+        self.preset = preset;
+        self.update();
+    }
+
+    /// Change a single band, ignored if there is no band `index`
+    pub fn set_band(&mut self, index: usize, band: Band) {
+        let Some(slot) = self.preset.bands.get_mut(index) else {
+            return;
+        };
+        *slot = band;
+        self.set_coefficients(index, Coefficients::new(&band, self.sample_rate));
+    }
+
+    pub fn coefficients(&self) -> &[Coefficients; BANDS] {
+        &self.coefficients
+    }
+
+    /// Recompute the coefficients if the rate of the track changed
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        if sample_rate != self.sample_rate {
+            self.sample_rate = sample_rate;
+            self.update();
+            self.reset();
+        }
+    }
+
+    fn update(&mut self) {
+        for index in 0..BANDS {
+            let band = self.preset.bands[index];
+            self.set_coefficients(index, Coefficients::new(&band, self.sample_rate));
+        }
+    }
+
+    fn set_coefficients(&mut self, index: usize, coefficients: Coefficients) {
+        // A skipped band kept its history from before it was flattened
+        if self.coefficients[index] == Coefficients::IDENTITY {
+            self.state[index] = [State::default(); MAX_CHANNELS];
+        }
+        self.coefficients[index] = coefficients;
+    }
+}
+
+impl Processor for Equalizer {
+    fn configure(&mut self, sample_rate: u32, _channels: usize) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    fn process(&mut self, pcm: &mut [f32], channels: usize) {
+        let channels = channels.clamp(1, MAX_CHANNELS);
+        for (coefficients, state) in self.coefficients.iter().zip(self.state.iter_mut()) {
+            // Flat bands are skipped entirely
+            if *coefficients == Coefficients::IDENTITY {
+                continue;
+            }
+            for frame in pcm.chunks_exact_mut(channels) {
+                for (sample, state) in frame.iter_mut().zip(state.iter_mut()) {
+                    *sample = state.process(coefficients, *sample);
+                }
+            }
+        }
+    }
+
+    /// Clear the filter history
+    fn reset(&mut self) {
+        self.state = [[State::default(); MAX_CHANNELS]; BANDS];
+    }
+}
@@ -0,0 +1,92 @@
+//! Loudness normalisation from ReplayGain values
+
+use super::tags::ReplayGain;
+
+/// Convert a gain in dB to a linear amplitude factor
+pub fn db_to_linear(db: f32) -> f32 {
+    libm::powf(10., db / 20.)
+}
+
+/// Which set of ReplayGain values to normalise with
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    /// Every track at the same loudness
+    Track,
+    /// Keep the loudness differences between tracks of an album
+    Album,
+}
+
+impl ReplayGainMode {
+    /// Linear gain for a track with `values`
+    ///
+    /// Falls back to the values of the other mode when the preferred ones are missing, and to
+    /// unity for untagged tracks. The gain is limited so the peak does not clip.
+    pub fn gain(self, values: &ReplayGain) -> f32 {
+        let track = (values.track_gain, values.track_peak);
+        let album = (values.album_gain, values.album_peak);
+        let (gain, peak) = match self {
+            Self::Off => return 1.,
+            Self::Track if track.0.is_some() => track,
+            Self::Track => album,
+            Self::Album if album.0.is_some() => album,
+            Self::Album => track,
+        };
+
+        let gain = gain.map_or(1., db_to_linear);
+        match peak.filter(|&peak| peak > 0.) {
+            Some(peak) => gain.min(1. / peak),
+            None => gain,
+        }
+    }
+}
+
+/// Gain stage applying the ReplayGain of the playing track
+#[derive(Debug, Clone, PartialEq)]
+pub struct Normalizer {
+    mode: ReplayGainMode,
+    values: ReplayGain,
+    gain: f32,
+}
+
+impl Normalizer {
+    pub const fn new(mode: ReplayGainMode) -> Self {
+        Self {
+            mode,
+            values: ReplayGain {
+                track_gain: None,
+                track_peak: None,
+                album_gain: None,
+                album_peak: None,
+            },
+            gain: 1.,
+        }
+    }
+
+    pub fn mode(&self) -> ReplayGainMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: ReplayGainMode) {
+        self.mode = mode;
+        self.gain = mode.gain(&self.values);
+    }
+
+    /// Switch to the values of a new track
+    pub fn set_values(&mut self, values: ReplayGain) {
+        self.values = values;
+        self.gain = self.mode.gain(&values);
+    }
+
+    /// Linear gain to apply to the samples
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Self::new(ReplayGainMode::default())
+    }
+}
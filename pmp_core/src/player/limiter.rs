@@ -0,0 +1,168 @@
+//! Look-ahead peak limiter keeping samples within full scale before quantisation
+
+use super::{dsp::Processor, resampler::MAX_CHANNELS};
+
+/// Longest look-ahead in frames
+pub const MAX_LOOKAHEAD: usize = 64;
+/// Highest output peak, just below full scale to leave room for dither
+pub const DEFAULT_THRESHOLD: f32 = 0.98;
+
+/// Time the gain takes to recover after a peak, in seconds
+const RELEASE: f32 = 0.05;
+/// Gain treated as fully recovered, 0.001 dB below unity
+const RELEASED: f32 = 0.99989;
+
+/// Limits peaks by lowering the gain ahead of them
+///
+/// The gain needed for each frame goes through a sliding minimum and then a moving average,
+/// both as long as the look-ahead. The audio is delayed by one frame less than that, which is
+/// the smallest delay where the averaged gain is never above what any frame needs, so the gain
+/// ramps down linearly before a peak and the output never exceeds the threshold.
+pub struct Limiter {
+    threshold: f32,
+    lookahead: usize,
+    /// Per frame step of the gain back towards unity
+    release: f32,
+    channels: usize,
+    /// Frames processed, wrapping
+    position: u32,
+    /// Slot of the current frame in the delay line and moving average
+    slot: usize,
+    delay: [[f32; MAX_CHANNELS]; MAX_LOOKAHEAD],
+    /// Increasing gains of the sliding minimum with the position they were needed at, as a
+    /// ring starting at `minima_start`
+    minima: [(u32, f32); MAX_LOOKAHEAD],
+    minima_start: usize,
+    minima_len: usize,
+    envelope: f32,
+    averages: [f32; MAX_LOOKAHEAD],
+    sum: f32,
+    limited: u64,
+}
+
+impl Limiter {
+    /// Limiter for audio at `sample_rate` with a look-ahead of about 1.5 ms
+    pub fn new(sample_rate: u32) -> Self {
+        let lookahead = (sample_rate as usize * 3 / 2000).clamp(1, MAX_LOOKAHEAD);
+        Self {
+            threshold: DEFAULT_THRESHOLD,
+            lookahead,
+            release: 1. - libm::expf(-1. / (RELEASE * sample_rate as f32)),
+            channels: 1,
+            position: 0,
+            slot: 0,
+            delay: [[0.; MAX_CHANNELS]; MAX_LOOKAHEAD],
+            minima: [(0, 1.); MAX_LOOKAHEAD],
+            minima_start: 0,
+            minima_len: 0,
+            envelope: 1.,
+            averages: [1.; MAX_LOOKAHEAD],
+            sum: lookahead as f32,
+            limited: 0,
+        }
+    }
+
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Delay added to the audio in frames
+    pub fn latency(&self) -> usize {
+        self.lookahead - 1
+    }
+
+    /// Samples output with reduced gain since the limiter was created
+    pub fn limited_samples(&self) -> u64 {
+        self.limited
+    }
+
+    /// Lowest gain needed by the frames in the look-ahead window, after adding `gain` for the
+    /// frame at `position`
+    fn push_minimum(&mut self, position: u32, gain: f32) -> f32 {
+        let len = self.lookahead;
+        // Only the front can have left the window
+        if self.minima_len > 0
+            && position.wrapping_sub(self.minima[self.minima_start].0) >= len as u32
+        {
+            self.minima_start = (self.minima_start + 1) % len;
+            self.minima_len -= 1;
+        }
+
+        while self.minima_len > 0 {
+            let back = (self.minima_start + self.minima_len - 1) % len;
+            if self.minima[back].1 < gain {
+                break;
+            }
+            self.minima_len -= 1;
+        }
+        self.minima[(self.minima_start + self.minima_len) % len] = (position, gain);
+        self.minima_len += 1;
+        self.minima[self.minima_start].1
+    }
+}
+
+impl Processor for Limiter {
+    fn configure(&mut self, _sample_rate: u32, channels: usize) {
+        let channels = channels.clamp(1, MAX_CHANNELS);
+        if channels != self.channels {
+            self.channels = channels;
+            self.reset();
+        }
+    }
+
+    fn process(&mut self, pcm: &mut [f32], channels: usize) {
+        self.configure(0, channels);
+        let len = self.lookahead;
+
+        for frame in pcm.chunks_exact_mut(self.channels) {
+            let peak = frame
+                .iter()
+                .fold(0f32, |peak, sample| peak.max(sample.abs()));
+            let needed = if peak > self.threshold {
+                self.threshold / peak
+            } else {
+                1.
+            };
+
+            let position = self.position;
+            let minimum = self.push_minimum(position, needed);
+            self.envelope = minimum.min(self.envelope + (1. - self.envelope) * self.release);
+            // The release only approaches unity, finish it once the difference is inaudible
+            if self.envelope > RELEASED {
+                self.envelope = 1.;
+            }
+
+            let slot = self.slot;
+            self.sum += self.envelope - self.averages[slot];
+            self.averages[slot] = self.envelope;
+            if slot == len - 1 {
+                // Drop the rounding errors the running sum builds up
+                self.sum = self.averages[..len].iter().sum();
+            }
+            let gain = (self.sum / len as f32).min(1.);
+
+            // The new frame goes in as the oldest in the delay line comes out
+            self.delay[slot][..frame.len()].copy_from_slice(frame);
+            let delayed = self.delay[(slot + 1) % len];
+            for (sample, delayed) in frame.iter_mut().zip(delayed) {
+                *sample = delayed * gain;
+            }
+
+            if gain < 1. {
+                self.limited += frame.len() as u64;
+            }
+            self.position = position.wrapping_add(1);
+            self.slot = (slot + 1) % len;
+        }
+    }
+
+    /// Drop the delayed audio and recover full gain
+    fn reset(&mut self) {
+        self.delay = [[0.; MAX_CHANNELS]; MAX_LOOKAHEAD];
+        self.minima_len = 0;
+        self.envelope = 1.;
+        self.averages = [1.; MAX_LOOKAHEAD];
+        self.sum = self.lookahead as f32;
+    }
+}
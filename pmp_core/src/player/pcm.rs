@@ -0,0 +1,100 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::rng::XorShift;
+
+/// Bytes used by a single sample on the I2S bus (`DataFormat::Data16Channel16`)
+pub const BYTES_PER_SAMPLE: usize = 2;
+
+/// Scale between a full scale float sample and a 16-bit sample
+const FULL_SCALE: f32 = 32768.0;
+
+/// Triangular probability density function dither source
+///
+/// Produces noise of +-1 LSB with a triangular distribution by summing two uniform values,
+/// which decorrelates the quantisation error from the signal.
+pub struct Tpdf {
+    rng: XorShift,
+}
+
+impl Tpdf {
+    pub const fn new(seed: u32) -> Self {
+        Self {
+            rng: XorShift::new(seed),
+        }
+    }
+
+    /// Next dither value in LSBs, in the range (-1, 1)
+    pub fn sample(&mut self) -> f32 {
+        self.rng.uniform() - self.rng.uniform()
+    }
+}
+
+impl Default for Tpdf {
+    fn default() -> Self {
+        Self::new(0x1234_5678)
+    }
+}
+
+/// Convert a float sample with `dither` LSBs of noise added to 16-bit PCM, clipping anything
+/// outside of [-1, 1)
+pub fn quantize(sample: f32, dither: f32) -> i16 {
+    let scaled = sample * FULL_SCALE + dither;
+    // Round half away from zero, `as` saturates out of range values and maps NaN to 0
+    let rounded = if scaled < 0. {
+        scaled - 0.5
+    } else {
+        scaled + 0.5
+    };
+    rounded.clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+/// Sample format stage converting decoder output to little endian interleaved 16-bit PCM
+#[derive(Default)]
+pub struct Quantizer {
+    dither: Option<Tpdf>,
+}
+
+impl Quantizer {
+    pub const fn new() -> Self {
+        Self { dither: None }
+    }
+
+    pub fn with_dither(mut self, dither: Tpdf) -> Self {
+        self.dither = Some(dither);
+        self
+    }
+
+    pub fn set_dither(&mut self, dither: Option<Tpdf>) {
+        self.dither = dither;
+    }
+
+    /// Apply `gain` to `samples`, quantise them and write them into `out`
+    ///
+    /// Returns the number of bytes written, which is limited by the length of `out`.
+    pub fn write(&mut self, samples: &[f32], gain: f32, out: &mut [u8]) -> usize {
+        let n = samples.len().min(out.len() / BYTES_PER_SAMPLE);
+        for (sample, bytes) in samples[..n]
+            .iter()
+            .zip(out.chunks_exact_mut(BYTES_PER_SAMPLE))
+        {
+            let dither = self.dither.as_mut().map_or(0., Tpdf::sample);
+            LittleEndian::write_i16(bytes, quantize(sample * gain, dither));
+        }
+        n * BYTES_PER_SAMPLE
+    }
+
+    /// Like [`Self::write`] but writing every mono sample to both channels of a stereo frame
+    pub fn write_mono(&mut self, samples: &[f32], gain: f32, out: &mut [u8]) -> usize {
+        let n = samples.len().min(out.len() / (2 * BYTES_PER_SAMPLE));
+        for (sample, bytes) in samples[..n]
+            .iter()
+            .zip(out.chunks_exact_mut(2 * BYTES_PER_SAMPLE))
+        {
+            let dither = self.dither.as_mut().map_or(0., Tpdf::sample);
+            let value = quantize(sample * gain, dither);
+            LittleEndian::write_i16(&mut bytes[..BYTES_PER_SAMPLE], value);
+            LittleEndian::write_i16(&mut bytes[BYTES_PER_SAMPLE..], value);
+        }
+        n * 2 * BYTES_PER_SAMPLE
+    }
+}
@@ -0,0 +1,100 @@
+//! Position in a track counted in samples
+
+use core::{fmt, time::Duration};
+
+/// Position as a whole number of samples per channel at the sample rate of the track
+///
+/// Counting samples rather than seconds keeps positions exact however long a track plays, and
+/// avoids double precision maths which the ESP32 does in software.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlaybackPosition {
+    samples: u64,
+    sample_rate: u32,
+}
+
+impl PlaybackPosition {
+    pub const fn new(samples: u64, sample_rate: u32) -> Self {
+        Self {
+            samples,
+            sample_rate,
+        }
+    }
+
+    /// Position `millis` milliseconds in, rounded down to a sample
+    pub const fn from_millis(millis: u64, sample_rate: u32) -> Self {
+        Self::new(millis * sample_rate as u64 / 1000, sample_rate)
+    }
+
+    /// Position `duration` in, rounded down to a sample
+    pub fn from_duration(duration: Duration, sample_rate: u32) -> Self {
+        let rate = u64::from(sample_rate);
+        let samples =
+            duration.as_secs() * rate + u64::from(duration.subsec_nanos()) * rate / 1_000_000_000;
+        Self::new(samples, sample_rate)
+    }
+
+    pub const fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    pub const fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The same point in time at another sample rate, rounded down to a sample
+    pub const fn at_rate(&self, sample_rate: u32) -> Self {
+        if self.sample_rate == sample_rate || self.sample_rate == 0 {
+            return Self::new(self.samples, sample_rate);
+        }
+        Self::new(
+            self.samples * sample_rate as u64 / self.sample_rate as u64,
+            sample_rate,
+        )
+    }
+
+    /// Milliseconds in, rounded down
+    pub const fn as_millis(&self) -> u64 {
+        match self.sample_rate {
+            0 => 0,
+            rate => self.samples * 1000 / rate as u64,
+        }
+    }
+
+    pub const fn as_duration(&self) -> Duration {
+        match self.sample_rate as u64 {
+            0 => Duration::ZERO,
+            rate => {
+                let nanos = (self.samples % rate) * 1_000_000_000 / rate;
+                Duration::new(self.samples / rate, nanos as u32)
+            }
+        }
+    }
+
+    /// Move on by `frames` samples per channel
+    pub fn advance(&mut self, frames: usize) {
+        self.samples += frames as u64;
+    }
+
+    /// Time from `earlier` to this position, zero if `earlier` is later
+    pub fn saturating_sub(self, earlier: Self) -> Self {
+        let earlier = earlier.at_rate(self.sample_rate);
+        Self::new(
+            self.samples.saturating_sub(earlier.samples),
+            self.sample_rate,
+        )
+    }
+
+    /// Whole minutes and the seconds past them
+    pub const fn minutes_seconds(&self) -> (u64, u64) {
+        let seconds = self.as_millis() / 1000;
+        (seconds / 60, seconds % 60)
+    }
+}
+
+/// Formats as `mm:ss`, minutes carry on past 99 rather than rolling over into hours
+impl fmt::Display for PlaybackPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (minutes, seconds) = self.minutes_seconds();
+        write!(f, "{minutes:02}:{seconds:02}")
+    }
+}
@@ -0,0 +1,329 @@
+//! Order tracks are played in
+
+use heapless::Vec;
+
+use super::rng::XorShift;
+
+/// Most tracks in the queue of the app
+pub const MAX_QUEUE: usize = 256;
+
+/// What happens at the end of a track or the queue
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatMode {
+    /// Stop after the last track
+    #[default]
+    Off,
+    /// Play the current track again when it ends
+    One,
+    /// Start again from the first track after the last
+    All,
+}
+
+impl RepeatMode {
+    pub fn cycle(self) -> Self {
+        match self {
+            Self::Off => Self::All,
+            Self::All => Self::One,
+            Self::One => Self::Off,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::One => "One",
+            Self::All => "All",
+        }
+    }
+}
+
+/// Up to `N` items to play in order, with a current position
+///
+/// Nothing is current until the queue is first moved with [`Queue::next`] or [`Queue::jump`].
+/// The items can be shuffled and put back in their original order, which is kept track of as
+/// items are added.
+#[derive(Debug, Clone)]
+pub struct Queue<T, const N: usize> {
+    items: Vec<T, N>,
+    /// Position of each item in the unshuffled order
+    origins: Vec<usize, N>,
+    current: Option<usize>,
+    repeat: RepeatMode,
+    shuffled: bool,
+}
+
+impl<T, const N: usize> Queue<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            origins: Vec::new(),
+            current: None,
+            repeat: RepeatMode::Off,
+            shuffled: false,
+        }
+    }
+
+    /// Queue of the first `N` of `items`
+    pub fn from_items(items: impl IntoIterator<Item = T>) -> Self {
+        let mut queue = Self::new();
+        queue.items.extend(items.into_iter().take(N));
+        queue.origins.extend(0..queue.items.len());
+        queue
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.origins.clear();
+        self.current = None;
+        self.shuffled = false;
+    }
+
+    pub fn repeat(&self) -> RepeatMode {
+        self.repeat
+    }
+
+    pub fn set_repeat(&mut self, repeat: RepeatMode) {
+        self.repeat = repeat;
+    }
+
+    pub fn current_index(&self) -> Option<usize> {
+        self.current
+    }
+
+    pub fn current(&self) -> Option<&T> {
+        self.items.get(self.current?)
+    }
+
+    /// Add `item` at the end, returns it back if the queue is full
+    pub fn append(&mut self, item: T) -> Result<(), T> {
+        self.insert(self.items.len(), self.items.len(), item)
+    }
+
+    /// Add `item` to play after the current one, returns it back if the queue is full
+    ///
+    /// When shuffled it also follows the current item in the original order.
+    pub fn play_next(&mut self, item: T) -> Result<(), T> {
+        match self.current {
+            Some(current) => self.insert(current + 1, self.origins[current] + 1, item),
+            None => self.insert(0, 0, item),
+        }
+    }
+
+    /// Insert `item` at `index`, and at `origin` in the unshuffled order
+    fn insert(&mut self, index: usize, origin: usize, item: T) -> Result<(), T> {
+        if self.items.is_full() {
+            return Err(item);
+        }
+        for other in self.origins.iter_mut().filter(|other| **other >= origin) {
+            *other += 1;
+        }
+        self.items.insert(index, item)?;
+        let _ = self.origins.insert(index, origin);
+        Ok(())
+    }
+
+    /// Take the item at `index` out of the queue, such as a track that cannot be played
+    ///
+    /// If it was current, the item [`Queue::next`] would have moved to becomes current.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        if index >= self.items.len() {
+            return None;
+        }
+        let origin = self.origins.remove(index);
+        for other in self.origins.iter_mut().filter(|other| **other > origin) {
+            *other -= 1;
+        }
+        let item = self.items.remove(index);
+
+        self.current = match self.current {
+            Some(current) if current > index => Some(current - 1),
+            // The following item took its place
+            Some(current) if current == index => {
+                self.current = index.checked_sub(1);
+                self.following()
+            }
+            current => current,
+        };
+        Some(item)
+    }
+
+    /// Make the item at `index` current, `None` if there is no such item
+    pub fn jump(&mut self, index: usize) -> Option<&T> {
+        if index >= self.items.len() {
+            return None;
+        }
+        self.current = Some(index);
+        self.current()
+    }
+
+    /// Index [`Queue::next`] would move to, wrapping around unless repeat is off
+    fn following(&self) -> Option<usize> {
+        let index = self.current.map_or(0, |current| current + 1);
+        if index < self.items.len() {
+            Some(index)
+        } else if self.repeat != RepeatMode::Off && !self.items.is_empty() {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    /// Index [`Queue::advance`] would move to
+    pub fn upcoming(&self) -> Option<usize> {
+        match (self.repeat, self.current) {
+            (RepeatMode::One, Some(current)) => Some(current),
+            _ => self.following(),
+        }
+    }
+
+    /// Skip to the next item, wrapping around unless repeat is off
+    ///
+    /// At the end of the queue with repeat off nothing is current any more and `None` is returned.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&T> {
+        self.current = self.following();
+        self.current()
+    }
+
+    /// Move on once the current item has finished playing, which repeats it with
+    /// [`RepeatMode::One`] and is otherwise the same as [`Queue::next`]
+    pub fn advance(&mut self) -> Option<&T> {
+        self.current = self.upcoming();
+        self.current()
+    }
+
+    /// Item [`Queue::advance`] would move to, to open it ahead of time for gapless playback
+    pub fn peek_advance(&self) -> Option<&T> {
+        self.items.get(self.upcoming()?)
+    }
+
+    /// Go back to the previous item, wrapping around to the last with [`RepeatMode::All`]
+    ///
+    /// The first item stays current otherwise.
+    pub fn previous(&mut self) -> Option<&T> {
+        let last = self.items.len().checked_sub(1)?;
+        self.current = Some(match self.current {
+            Some(0) if self.repeat == RepeatMode::All => last,
+            Some(current) => current.saturating_sub(1),
+            None => last,
+        });
+        self.current()
+    }
+
+    pub fn is_shuffled(&self) -> bool {
+        self.shuffled
+    }
+
+    /// Shuffle every item with a Fisher-Yates shuffle, so each plays once before any repeats
+    ///
+    /// The current item moves to the front and stays current.
+    pub fn shuffle(&mut self, rng: &mut XorShift) {
+        let start = match self.current {
+            Some(current) => {
+                self.swap(0, current);
+                self.current = Some(0);
+                1
+            }
+            None => 0,
+        };
+        for i in (start + 1..self.items.len()).rev() {
+            let j = start + rng.below(i + 1 - start);
+            self.swap(i, j);
+        }
+        self.shuffled = true;
+    }
+
+    /// Shuffle whole albums, keeping the tracks of each in their original order
+    ///
+    /// Albums are runs of items next to each other in the original order with the same
+    /// `album`. The album of the current item moves to the front and the item stays current.
+    pub fn shuffle_albums<K: PartialEq>(&mut self, rng: &mut XorShift, album: impl Fn(&T) -> K) {
+        self.unshuffle();
+
+        // Start and end of each album
+        let mut albums: Vec<(usize, usize), N> = Vec::new();
+        for (i, item) in self.items.iter().enumerate() {
+            match albums.last_mut() {
+                Some((start, end)) if album(&self.items[*start]) == album(item) => *end = i + 1,
+                _ => {
+                    let _ = albums.push((i, i + 1));
+                }
+            }
+        }
+
+        let start = match self.current {
+            Some(current) => {
+                let playing = albums
+                    .iter()
+                    .position(|&(_, end)| current < end)
+                    .unwrap_or(0);
+                albums.swap(0, playing);
+                1
+            }
+            None => 0,
+        };
+        for i in (start + 1..albums.len()).rev() {
+            let j = start + rng.below(i + 1 - start);
+            albums.swap(i, j);
+        }
+
+        let mut order: Vec<usize, N> = Vec::new();
+        for &(start, end) in &albums {
+            order.extend(start..end);
+        }
+        self.permute(&order);
+        self.shuffled = true;
+    }
+
+    /// Put the items back in their original order, the current item stays current
+    pub fn unshuffle(&mut self) {
+        if !self.shuffled {
+            return;
+        }
+        let mut order: Vec<usize, N> = Vec::new();
+        let _ = order.resize(self.items.len(), 0);
+        for (i, &origin) in self.origins.iter().enumerate() {
+            order[origin] = i;
+        }
+        self.permute(&order);
+        self.shuffled = false;
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.items.swap(a, b);
+        self.origins.swap(a, b);
+    }
+
+    /// Rearrange the items so the one at `order[i]` ends up at `i`, following the current item
+    fn permute(&mut self, order: &[usize]) {
+        if let Some(current) = self.current {
+            self.current = order.iter().position(|&i| i == current);
+        }
+        for i in 0..order.len() {
+            // Earlier swaps moved the wanted item on from where it started
+            let mut j = order[i];
+            while j < i {
+                j = order[j];
+            }
+            self.swap(i, j);
+        }
+    }
+}
+
+impl<T, const N: usize> Default for Queue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,167 @@
+use core::f32::consts::PI;
+
+/// Maximum number of interleaved channels the resampler can handle
+pub const MAX_CHANNELS: usize = 2;
+
+/// Filter length in input frames
+const TAPS: usize = 16;
+/// Number of precomputed fractional delays, intermediate delays are linearly interpolated
+const PHASES: usize = 32;
+/// One input frame in 32.32 fixed point
+const ONE: u64 = 1 << 32;
+
+/// Polyphase windowed sinc resampler for interleaved float PCM
+///
+/// Used to convert tracks to the rate of the [`super::Sink`], since the circular I2S transfer
+/// cannot be reconfigured once started.
+pub struct Resampler {
+    from: u32,
+    to: u32,
+    channels: usize,
+    /// Input frames advanced per output frame in 32.32 fixed point
+    step: u64,
+    /// Position of the next output frame relative to the centre of the history in 32.32 fixed
+    /// point, the integer part is the number of input frames to consume first
+    pos: u64,
+    history: [[f32; TAPS]; MAX_CHANNELS],
+    filter: [[f32; TAPS]; PHASES + 1],
+}
+
+impl Resampler {
+    /// Create a resampler producing `to` Hz, initially passing audio through unchanged
+    pub fn new(to: u32) -> Self {
+        let mut resampler = Self {
+            from: to,
+            to,
+            channels: 1,
+            step: ONE,
+            pos: 0,
+            history: [[0.; TAPS]; MAX_CHANNELS],
+            filter: [[0.; TAPS]; PHASES + 1],
+        };
+        resampler.build_filter();
+        resampler
+    }
+
+    /// Output sample rate
+    pub fn output_rate(&self) -> u32 {
+        self.to
+    }
+
+    /// True if input is passed through without resampling
+    pub fn is_passthrough(&self) -> bool {
+        self.from == self.to
+    }
+
+    /// Set the input format, resetting the filter state if it changed
+    pub fn configure(&mut self, from: u32, channels: usize) {
+        let channels = channels.clamp(1, MAX_CHANNELS);
+        if from == self.from && channels == self.channels {
+            return;
+        }
+        self.from = from;
+        self.channels = channels;
+        self.step = (u64::from(from) << 32) / u64::from(self.to);
+        self.build_filter();
+        self.reset();
+    }
+
+    /// Clear the filter history
+    pub fn reset(&mut self) {
+        self.pos = 0;
+        self.history = [[0.; TAPS]; MAX_CHANNELS];
+    }
+
+    fn build_filter(&mut self) {
+        // Low pass below the lower of the two nyquist frequencies, leaving room for the
+        // transition band of the short filter
+        let cutoff = (self.to as f32 / self.from as f32).min(1.) * 0.92;
+        let half = (TAPS / 2) as f32;
+
+        for (phase, taps) in self.filter.iter_mut().enumerate() {
+            let frac = phase as f32 / PHASES as f32;
+            for (k, tap) in taps.iter_mut().enumerate() {
+                let x = half - 1. - k as f32 + frac;
+                *tap = cutoff * sinc(cutoff * x) * blackman(x / half);
+            }
+            // Unity gain at DC for every phase
+            let sum: f32 = taps.iter().sum();
+            taps.iter_mut().for_each(|tap| *tap /= sum);
+        }
+    }
+
+    fn push(&mut self, frame: &[f32]) {
+        for (history, sample) in self.history.iter_mut().zip(frame) {
+            history.copy_within(1.., 0);
+            history[TAPS - 1] = *sample;
+        }
+    }
+
+    /// Resample interleaved `input` into `output`
+    ///
+    /// Returns the number of input and output samples used. Call again with the remaining input
+    /// when `output` fills up before `input` is consumed.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) -> (usize, usize) {
+        if self.is_passthrough() {
+            let n = input.len().min(output.len());
+            output[..n].copy_from_slice(&input[..n]);
+            return (n, n);
+        }
+
+        let channels = self.channels;
+        let mut frames = input.chunks_exact(channels);
+        let mut consumed = 0;
+        let mut produced = 0;
+
+        while produced + channels <= output.len() {
+            while self.pos >= ONE {
+                match frames.next() {
+                    Some(frame) => {
+                        self.push(frame);
+                        consumed += channels;
+                        self.pos -= ONE;
+                    }
+                    None => return (input.len(), produced),
+                }
+            }
+
+            let phase = (self.pos as u32) as f32 * (PHASES as f32 / ONE as f32);
+            let index = (phase as usize).min(PHASES - 1);
+            let weight = phase - index as f32;
+            let (lower, upper) = (&self.filter[index], &self.filter[index + 1]);
+
+            for (history, out) in self.history[..channels]
+                .iter()
+                .zip(&mut output[produced..produced + channels])
+            {
+                *out = history
+                    .iter()
+                    .zip(lower.iter().zip(upper))
+                    .map(|(x, (a, b))| x * (a + weight * (b - a)))
+                    .sum();
+            }
+
+            produced += channels;
+            self.pos += self.step;
+        }
+
+        (consumed, produced)
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.
+    } else {
+        libm::sinf(PI * x) / (PI * x)
+    }
+}
+
+/// Blackman window over [-1, 1]
+fn blackman(x: f32) -> f32 {
+    if x.abs() >= 1. {
+        0.
+    } else {
+        0.42 + 0.5 * libm::cosf(PI * x) + 0.08 * libm::cosf(2. * PI * x)
+    }
+}
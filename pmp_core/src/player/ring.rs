@@ -0,0 +1,192 @@
+//! Lock-free single producer, single consumer ring buffer of PCM samples
+
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+/// Ring of `N` samples, shared between one [`Producer`] and one [`Consumer`]
+///
+/// Both ends copy whole slices and only ever store their own index, so they can run in different
+/// tasks or interrupts without a lock. `N` must be a power of two.
+pub struct Ring<const N: usize> {
+    buffer: UnsafeCell<[f32; N]>,
+    /// Samples written, wrapping
+    head: AtomicUsize,
+    /// Samples read, wrapping
+    tail: AtomicUsize,
+    /// Everything written before this head is discarded by the consumer
+    flush: AtomicUsize,
+    split: AtomicBool,
+}
+
+// Safety: the producer only writes the free part of the buffer and the consumer only reads the
+// filled part, the indices marking the boundary are atomic
+unsafe impl<const N: usize> Sync for Ring<N> {}
+
+impl<const N: usize> Ring<N> {
+    pub const fn new() -> Self {
+        const { assert!(N.is_power_of_two()) };
+        Self {
+            buffer: UnsafeCell::new([0.; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            flush: AtomicUsize::new(0),
+            split: AtomicBool::new(false),
+        }
+    }
+
+    /// Take both ends, `None` if they were already taken
+    pub fn split(&self) -> Option<(Producer<'_, N>, Consumer<'_, N>)> {
+        if self.split.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        Some((
+            Producer { ring: self },
+            Consumer {
+                ring: self,
+                watermark: 0,
+                filling: true,
+            },
+        ))
+    }
+
+    /// Samples waiting to be read
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        head.wrapping_sub(self.tail.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize> Default for Ring<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Writing end of a [`Ring`]
+pub struct Producer<'a, const N: usize> {
+    ring: &'a Ring<N>,
+}
+
+impl<const N: usize> Producer<'_, N> {
+    /// Append as many of `samples` as fit, returns the number written
+    pub fn write(&mut self, samples: &[f32]) -> usize {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        let n = samples.len().min(N - head.wrapping_sub(tail));
+
+        let start = head % N;
+        let first = n.min(N - start);
+        // Safety: the consumer does not read past `head`, so this region is ours until it moves
+        let buffer = unsafe { &mut *self.ring.buffer.get() };
+        buffer[start..start + first].copy_from_slice(&samples[..first]);
+        buffer[..n - first].copy_from_slice(&samples[first..n]);
+
+        self.ring
+            .head
+            .store(head.wrapping_add(n), Ordering::Release);
+        n
+    }
+
+    /// Room left in samples
+    pub fn free(&self) -> usize {
+        N - self.ring.len()
+    }
+
+    /// Have the consumer drop everything written so far, after a seek or track change
+    pub fn flush(&mut self) {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        self.ring.flush.store(head, Ordering::Release);
+    }
+}
+
+/// Reading end of a [`Ring`]
+pub struct Consumer<'a, const N: usize> {
+    ring: &'a Ring<N>,
+    watermark: usize,
+    /// Waiting for the watermark before reading again
+    filling: bool,
+}
+
+impl<const N: usize> Consumer<'_, N> {
+    /// Samples to wait for before reading, at first and whenever the ring runs dry
+    ///
+    /// Waiting for a few buffers to build up turns a producer that keeps falling just behind into
+    /// one longer gap rather than many short ones. Clamped to the capacity.
+    pub fn set_watermark(&mut self, watermark: usize) {
+        self.watermark = watermark.min(N);
+    }
+
+    pub fn watermark(&self) -> usize {
+        self.watermark
+    }
+
+    /// Samples waiting to be read
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    /// Samples before the point a [`Producer::flush`] asked to drop everything up to, `None` if
+    /// no flush is pending
+    pub fn flush_pending(&self) -> Option<usize> {
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        let ahead = self.ring.flush.load(Ordering::Acquire).wrapping_sub(tail);
+        // A flush point behind the tail has already been passed
+        (ahead > 0 && ahead <= self.len()).then_some(ahead)
+    }
+
+    /// Drop the samples before a pending flush point
+    pub fn flush(&mut self) {
+        if let Some(ahead) = self.flush_pending() {
+            let tail = self.ring.tail.load(Ordering::Relaxed);
+            self.ring
+                .tail
+                .store(tail.wrapping_add(ahead), Ordering::Release);
+        }
+    }
+
+    /// Read into `out`, returns the number of samples read
+    ///
+    /// Nothing is read while filling up to the watermark, and a read stops at a pending flush
+    /// point so that audio after it is not played before the flush.
+    pub fn read(&mut self, out: &mut [f32]) -> usize {
+        let available = self.len();
+        if self.filling {
+            if available < self.watermark.max(1) {
+                return 0;
+            }
+            self.filling = false;
+        }
+        let available = self.flush_pending().unwrap_or(available);
+        let n = out.len().min(available);
+        if n == available && self.flush_pending().is_none() {
+            self.filling = true;
+        }
+
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        let start = tail % N;
+        let first = n.min(N - start);
+        // Safety: the producer does not write before `tail`, so this region is ours until it moves
+        let buffer = unsafe { &*self.ring.buffer.get() };
+        out[..first].copy_from_slice(&buffer[start..start + first]);
+        out[first..n].copy_from_slice(&buffer[..n - first]);
+
+        self.ring
+            .tail
+            .store(tail.wrapping_add(n), Ordering::Release);
+        n
+    }
+}
@@ -0,0 +1,39 @@
+//! Small seedable random number generator for dither and shuffling
+
+/// Marsaglia's xorshift32, fast and good enough for noise and shuffling but not for anything
+/// security related
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XorShift {
+    state: u32,
+}
+
+impl XorShift {
+    pub const fn new(seed: u32) -> Self {
+        // xorshift gets stuck on zero
+        Self {
+            state: if seed == 0 { 0x9E37_79B9 } else { seed },
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    /// Uniform value in [0, 1)
+    pub fn uniform(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// Uniform index in [0, `n`), `n` must be non-zero
+    ///
+    /// Uses the high bits of a multiply rather than a modulo, the bias this leaves is below
+    /// `n / 2^32`.
+    pub fn below(&mut self, n: usize) -> usize {
+        ((u64::from(self.next_u32()) * n as u64) >> 32) as usize
+    }
+}
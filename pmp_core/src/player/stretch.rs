@@ -0,0 +1,208 @@
+//! Playback speed changes that keep the pitch, for spoken word
+
+use core::f32::consts::PI;
+
+use super::resampler::MAX_CHANNELS;
+
+pub const MIN_SPEED: f32 = 0.75;
+pub const MAX_SPEED: f32 = 2.;
+
+/// Output frames produced per segment, half the window
+pub const HOP: usize = 320;
+/// Segment length in frames, long enough to hold a few pitch periods of a voice
+const WINDOW: usize = 2 * HOP;
+/// Frames a segment may be moved either way to line up with the previous one
+const SEEK: usize = 160;
+/// Input kept in frames, covers the previous segment and the search range at top speed
+const BUFFER: usize = 1536;
+
+/// Waveform similarity overlap-add (WSOLA) time stretcher for interleaved float PCM
+///
+/// Segments of the input are taken `speed` hops apart and overlap-added a hop apart. Each one
+/// is shifted within [`SEEK`] frames to best match the audio that followed the previous segment,
+/// which keeps the waveform continuous so the pitch is unchanged.
+pub struct TimeStretch {
+    speed: f32,
+    channels: usize,
+    window: [f32; WINDOW],
+    buffer: [[f32; MAX_CHANNELS]; BUFFER],
+    /// Frames in `buffer`
+    len: usize,
+    /// Nominal start of the next segment in `buffer`
+    analysis: f32,
+    /// Start of the audio that followed the previous segment in `buffer`
+    natural: Option<usize>,
+    /// Faded out second half of the previous segment
+    overlap: [[f32; MAX_CHANNELS]; HOP],
+}
+
+impl TimeStretch {
+    /// Create a time stretcher, initially passing audio through at normal speed
+    pub fn new() -> Self {
+        let mut window = [0.; WINDOW];
+        // Periodic Hann, overlapping halves add up to exactly one
+        for (n, weight) in window.iter_mut().enumerate() {
+            *weight = 0.5 - 0.5 * libm::cosf(2. * PI * n as f32 / WINDOW as f32);
+        }
+        Self {
+            speed: 1.,
+            channels: 1,
+            window,
+            buffer: [[0.; MAX_CHANNELS]; BUFFER],
+            len: 0,
+            analysis: 0.,
+            natural: None,
+            overlap: [[0.; MAX_CHANNELS]; HOP],
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Set the speed, clamped to [`MIN_SPEED`] and [`MAX_SPEED`]
+    ///
+    /// Audio buffered for stretching is dropped when returning to normal speed.
+    pub fn set_speed(&mut self, speed: f32) {
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        if self.is_passthrough() != (speed == 1.) {
+            self.reset();
+        }
+        self.speed = speed;
+    }
+
+    /// True if input is passed through unchanged
+    pub fn is_passthrough(&self) -> bool {
+        self.speed == 1.
+    }
+
+    /// Set the number of interleaved channels, resetting if it changed
+    pub fn configure(&mut self, channels: usize) {
+        let channels = channels.clamp(1, MAX_CHANNELS);
+        if channels != self.channels {
+            self.channels = channels;
+            self.reset();
+        }
+    }
+
+    /// Input frames taken in but not played out yet, the delay the stretcher adds
+    pub fn buffered(&self) -> usize {
+        if self.is_passthrough() {
+            return 0;
+        }
+        let played = self.natural.unwrap_or(self.analysis as usize);
+        self.len.saturating_sub(played)
+    }
+
+    /// Drop buffered audio, after a seek
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.analysis = 0.;
+        self.natural = None;
+        self.overlap = [[0.; MAX_CHANNELS]; HOP];
+    }
+
+    /// Stretch interleaved `input` into `output`
+    ///
+    /// Returns the number of input and output samples used, output is produced [`HOP`] frames
+    /// at a time. Call again with the remaining input until neither is used.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) -> (usize, usize) {
+        if self.is_passthrough() {
+            let n = input.len().min(output.len());
+            output[..n].copy_from_slice(&input[..n]);
+            return (n, n);
+        }
+
+        let channels = self.channels;
+        let mut consumed = 0;
+        let mut produced = 0;
+        loop {
+            let frames = ((input.len() - consumed) / channels).min(BUFFER - self.len);
+            for (frame, buffered) in input[consumed..]
+                .chunks_exact(channels)
+                .zip(&mut self.buffer[self.len..self.len + frames])
+            {
+                buffered[..channels].copy_from_slice(frame);
+            }
+            self.len += frames;
+            consumed += frames * channels;
+
+            if output.len() - produced < HOP * channels || !self.step_ready() {
+                return (consumed, produced);
+            }
+            self.step(&mut output[produced..produced + HOP * channels]);
+            produced += HOP * channels;
+        }
+    }
+
+    fn step_ready(&self) -> bool {
+        let reach = if self.natural.is_some() { SEEK } else { 0 };
+        self.analysis as usize + reach + WINDOW <= self.len
+    }
+
+    /// Overlap-add the next segment, writing a hop of output
+    fn step(&mut self, output: &mut [f32]) {
+        let channels = self.channels;
+        let nominal = self.analysis as usize;
+        let start = match self.natural {
+            Some(natural) => self.search(natural, nominal),
+            None => nominal,
+        };
+
+        let segment = &self.buffer[start..start + WINDOW];
+        let (head, tail) = self.window.split_at(HOP);
+        for (i, out) in output.chunks_exact_mut(channels).enumerate() {
+            for (c, out) in out.iter_mut().enumerate() {
+                *out = self.overlap[i][c] + head[i] * segment[i][c];
+            }
+        }
+        for ((overlap, weight), frame) in self.overlap.iter_mut().zip(tail).zip(&segment[HOP..]) {
+            for (overlap, sample) in overlap.iter_mut().zip(frame) {
+                *overlap = weight * sample;
+            }
+        }
+
+        self.natural = Some(start + HOP);
+        self.analysis += HOP as f32 * self.speed;
+
+        // Drop input before both the next search range and the natural continuation
+        let keep = (self.analysis as usize)
+            .saturating_sub(SEEK)
+            .min(start + HOP);
+        self.buffer.copy_within(keep..self.len, 0);
+        self.len -= keep;
+        self.analysis -= keep as f32;
+        self.natural = Some(start + HOP - keep);
+    }
+
+    /// Start within [`SEEK`] frames of `nominal` that best continues the audio at `natural`
+    fn search(&self, natural: usize, nominal: usize) -> usize {
+        let mono = |frame: &[f32; MAX_CHANNELS]| frame[..self.channels].iter().sum::<f32>();
+        let target = &self.buffer[natural..natural + HOP];
+
+        let low = nominal.saturating_sub(SEEK);
+        let high = (nominal + SEEK).min(self.len - WINDOW);
+        let mut best = (nominal.min(high), f32::MIN);
+        // Every other frame and offset is enough to find the alignment and halves the work twice
+        for start in (low..=high).step_by(2) {
+            let candidate = &self.buffer[start..start + HOP];
+            let (mut correlation, mut energy) = (0., 0.);
+            for (a, b) in target.iter().zip(candidate).step_by(2) {
+                let (a, b) = (mono(a), mono(b));
+                correlation += a * b;
+                energy += b * b;
+            }
+            let score = correlation / libm::sqrtf(energy + 1e-9);
+            if score > best.1 {
+                best = (start, score);
+            }
+        }
+        best.0
+    }
+}
+
+impl Default for TimeStretch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,547 @@
+use core::{ops::Range, time::Duration};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use heapless::{String, Vec};
+
+use crate::fs::Source;
+
+/// Capacity of text fields, longer values are truncated
+pub const MAX_TEXT: usize = 64;
+/// Most chapters kept per track
+pub const MAX_CHAPTERS: usize = 16;
+
+/// Largest frame or item body read into memory, larger ones are truncated or skipped
+const BODY_LEN: usize = 256;
+/// Longest APE item key read
+const APE_KEY_LEN: usize = 32;
+
+/// ReplayGain values in dB and linear peak amplitude
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Chapter {
+    pub start: Duration,
+    pub title: String<MAX_TEXT>,
+}
+
+/// Metadata of a track, gathered from ID3v2, APEv2 and ID3v1 tags in that order of precedence
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Tags {
+    pub title: String<MAX_TEXT>,
+    pub artist: String<MAX_TEXT>,
+    pub album: String<MAX_TEXT>,
+    pub track_number: Option<u16>,
+    pub replay_gain: ReplayGain,
+    pub chapters: Vec<Chapter, MAX_CHAPTERS>,
+}
+
+impl Tags {
+    fn set_title(&mut self, text: &str) {
+        set_if_empty(&mut self.title, text)
+    }
+
+    fn set_artist(&mut self, text: &str) {
+        set_if_empty(&mut self.artist, text)
+    }
+
+    fn set_album(&mut self, text: &str) {
+        set_if_empty(&mut self.album, text)
+    }
+
+    /// Set the track number from either `3` or `3/12`
+    fn set_track_number(&mut self, text: &str) {
+        if self.track_number.is_none() {
+            self.track_number = text.split('/').next().and_then(|n| n.trim().parse().ok());
+        }
+    }
+
+    /// Set a ReplayGain value from a user defined text frame or APE item
+    fn set_user_text(&mut self, key: &str, value: &str) {
+        let field = match key {
+            key if key.eq_ignore_ascii_case("REPLAYGAIN_TRACK_GAIN") => {
+                &mut self.replay_gain.track_gain
+            }
+            key if key.eq_ignore_ascii_case("REPLAYGAIN_TRACK_PEAK") => {
+                &mut self.replay_gain.track_peak
+            }
+            key if key.eq_ignore_ascii_case("REPLAYGAIN_ALBUM_GAIN") => {
+                &mut self.replay_gain.album_gain
+            }
+            key if key.eq_ignore_ascii_case("REPLAYGAIN_ALBUM_PEAK") => {
+                &mut self.replay_gain.album_peak
+            }
+            _ => return,
+        };
+        if field.is_none() {
+            *field = parse_gain(value);
+        }
+    }
+}
+
+/// Read all tags in `source`
+///
+/// Only the frames of interest are read into memory, everything else, like embedded artwork,
+/// is skipped by seeking over it. Returns the tags and the byte range of the audio between them.
+pub fn read<S: Source>(source: &mut S) -> Result<(Tags, Range<u32>), S::Error> {
+    let mut tags = Tags::default();
+    let start = read_id3v2(source, &mut tags)?;
+
+    let mut end = source.length();
+    let mut id3v1 = [0u8; 128];
+    let has_id3v1 = end >= start + 128
+        && read_at(source, end - 128, &mut id3v1)? == 128
+        && &id3v1[..3] == b"TAG";
+    if has_id3v1 {
+        end -= 128;
+    }
+
+    end = read_ape(source, start, end, &mut tags)?;
+    if has_id3v1 {
+        read_id3v1(&id3v1, &mut tags);
+    }
+
+    Ok((tags, start..end))
+}
+
+fn read_at<S: Source>(source: &mut S, offset: u32, buf: &mut [u8]) -> Result<usize, S::Error> {
+    source.seek_from_start(offset)?;
+    source.read_full(buf)
+}
+
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0u32, |size, byte| (size << 7) | u32::from(byte & 0x7F))
+}
+
+/// Length of the ID3v2 tag starting at `header`, including its header and footer
+pub fn id3v2_len(header: &[u8]) -> Option<u32> {
+    let header = header.get(..10)?;
+    if &header[..3] != b"ID3" {
+        return None;
+    }
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+    Some(10 + syncsafe(&header[6..10]) + footer)
+}
+
+#[derive(Clone, Copy)]
+enum Frame {
+    Title,
+    Artist,
+    Album,
+    TrackNumber,
+    UserText,
+    Chapter,
+}
+
+impl Frame {
+    fn from_id(id: &[u8]) -> Option<Self> {
+        Some(match id {
+            b"TIT2" | b"TT2" => Self::Title,
+            b"TPE1" | b"TP1" => Self::Artist,
+            b"TALB" | b"TAL" => Self::Album,
+            b"TRCK" | b"TRK" => Self::TrackNumber,
+            b"TXXX" | b"TXX" => Self::UserText,
+            b"CHAP" => Self::Chapter,
+            _ => return None,
+        })
+    }
+}
+
+/// Header of a frame inside of an ID3v2 tag of major version `version`
+struct FrameHeader<'h> {
+    id: &'h [u8],
+    size: u32,
+    flags: u16,
+}
+
+impl<'h> FrameHeader<'h> {
+    fn len(version: u8) -> usize {
+        if version == 2 {
+            6
+        } else {
+            10
+        }
+    }
+
+    fn parse(version: u8, header: &'h [u8]) -> Option<Self> {
+        let header = header.get(..Self::len(version))?;
+        Some(match version {
+            2 => Self {
+                id: &header[..3],
+                size: BigEndian::read_u24(&header[3..6]),
+                flags: 0,
+            },
+            3 => Self {
+                id: &header[..4],
+                size: BigEndian::read_u32(&header[4..8]),
+                flags: BigEndian::read_u16(&header[8..10]),
+            },
+            _ => Self {
+                id: &header[..4],
+                size: syncsafe(&header[4..8]),
+                flags: BigEndian::read_u16(&header[8..10]),
+            },
+        })
+    }
+
+    /// Strip the prefixes added by the frame flags and undo unsynchronisation, returns `None` for
+    /// compressed or encrypted frames
+    fn body<'b>(&self, version: u8, body: &'b mut [u8]) -> Option<&'b [u8]> {
+        let (unreadable, grouping, unsync, data_length) = match version {
+            3 => (0x00C0, 0x0020, 0, 0),
+            4 => (0x000C, 0x0040, 0x0002, 0x0001),
+            _ => (0, 0, 0, 0),
+        };
+        if self.flags & unreadable != 0 {
+            return None;
+        }
+        let skip = if self.flags & grouping != 0 { 1 } else { 0 }
+            + if self.flags & data_length != 0 { 4 } else { 0 };
+        let body = body.get_mut(skip..)?;
+        let len = if self.flags & unsync != 0 {
+            remove_unsync(body)
+        } else {
+            body.len()
+        };
+        Some(&body[..len])
+    }
+}
+
+/// Undo ID3v2 unsynchronisation in place, returning the new length
+fn remove_unsync(data: &mut [u8]) -> usize {
+    let mut len = 0;
+    for i in 0..data.len() {
+        if i > 0 && data[i] == 0 && data[i - 1] == 0xFF {
+            continue;
+        }
+        data[len] = data[i];
+        len += 1;
+    }
+    len
+}
+
+/// Parse the ID3v2 tag at the start of `source`, returns its length
+fn read_id3v2<S: Source>(source: &mut S, tags: &mut Tags) -> Result<u32, S::Error> {
+    let mut header = [0u8; 10];
+    if read_at(source, 0, &mut header)? < header.len() {
+        return Ok(0);
+    }
+    let Some(len) = id3v2_len(&header) else {
+        return Ok(0);
+    };
+
+    let (version, flags) = (header[3], header[5]);
+    // Whole tag unsynchronisation is only used by ID3v2.3 and older writers and changes frame
+    // sizes, just skip those tags
+    if !(2..=4).contains(&version) || (version < 4 && flags & 0x80 != 0) {
+        return Ok(len);
+    }
+
+    let end = 10 + syncsafe(&header[6..10]);
+    let mut offset = 10;
+    if version > 2 && flags & 0x40 != 0 {
+        let mut extended = [0u8; 4];
+        read_at(source, offset, &mut extended)?;
+        let extended_len = match version {
+            3 => BigEndian::read_u32(&extended).checked_add(4),
+            _ => Some(syncsafe(&extended)),
+        };
+        // A corrupt length runs past the end of the tag
+        let Some(next) = extended_len
+            .and_then(|extended_len| offset.checked_add(extended_len))
+            .filter(|&next| next <= end)
+        else {
+            return Ok(len);
+        };
+        offset = next;
+    }
+
+    let header_len = FrameHeader::len(version);
+    let mut frame = [0u8; 10];
+    let mut body = [0u8; BODY_LEN];
+    while offset
+        .checked_add(header_len as u32)
+        .is_some_and(|header_end| header_end <= end)
+    {
+        if read_at(source, offset, &mut frame[..header_len])? < header_len {
+            break;
+        }
+        let Some(header) = FrameHeader::parse(version, &frame) else {
+            break;
+        };
+        // Start of the padding
+        if header.id[0] == 0 {
+            break;
+        }
+
+        let body_offset = offset + header_len as u32;
+        match body_offset.checked_add(header.size) {
+            Some(next) if next <= end => offset = next,
+            _ => break,
+        }
+        let Some(kind) = Frame::from_id(header.id) else {
+            continue;
+        };
+
+        let read = read_at(
+            source,
+            body_offset,
+            &mut body[..(header.size as usize).min(BODY_LEN)],
+        )?;
+        if let Some(data) = header.body(version, &mut body[..read]) {
+            parse_frame(version, kind, data, tags);
+        }
+    }
+
+    Ok(len)
+}
+
+fn parse_frame(version: u8, kind: Frame, data: &[u8], tags: &mut Tags) {
+    let mut text = String::<MAX_TEXT>::new();
+    match kind {
+        Frame::Title => {
+            decode_text(data, &mut text);
+            tags.set_title(&text)
+        }
+        Frame::Artist => {
+            decode_text(data, &mut text);
+            tags.set_artist(&text)
+        }
+        Frame::Album => {
+            decode_text(data, &mut text);
+            tags.set_album(&text)
+        }
+        Frame::TrackNumber => {
+            decode_text(data, &mut text);
+            tags.set_track_number(&text)
+        }
+        Frame::UserText => {
+            let Some((&encoding, data)) = data.split_first() else {
+                return;
+            };
+            let (description, value) = split_terminated(encoding, data);
+            let mut key = String::<MAX_TEXT>::new();
+            decode(encoding, description, &mut key);
+            decode(encoding, value, &mut text);
+            tags.set_user_text(&key, &text)
+        }
+        Frame::Chapter => {
+            if let Some(chapter) = parse_chapter(version, data) {
+                let _ = tags.chapters.push(chapter);
+            }
+        }
+    }
+}
+
+/// Parse a CHAP frame, taking the title from its embedded TIT2 frame
+fn parse_chapter(version: u8, data: &[u8]) -> Option<Chapter> {
+    let id_end = data.iter().position(|byte| *byte == 0)?;
+    let times = data.get(id_end + 1..id_end + 17)?;
+    let mut chapter = Chapter {
+        start: Duration::from_millis(BigEndian::read_u32(&times[..4]).into()),
+        title: String::new(),
+    };
+
+    let mut frames = &data[id_end + 17..];
+    while let Some(header) = FrameHeader::parse(version, frames) {
+        let body = &frames[FrameHeader::len(version)..];
+        let size = (header.size as usize).min(body.len());
+        if header.id == b"TIT2" {
+            decode_text(&body[..size], &mut chapter.title);
+            break;
+        }
+        frames = &body[size..];
+    }
+    Some(chapter)
+}
+
+/// Split `data` at the string terminator of `encoding`
+fn split_terminated(encoding: u8, data: &[u8]) -> (&[u8], &[u8]) {
+    let end = match encoding {
+        1 | 2 => data
+            .chunks_exact(2)
+            .position(|pair| pair == [0, 0])
+            .map(|position| (position * 2, position * 2 + 2)),
+        _ => data
+            .iter()
+            .position(|byte| *byte == 0)
+            .map(|position| (position, position + 1)),
+    };
+    match end {
+        Some((end, rest)) => (&data[..end], &data[rest..]),
+        None => (data, &[]),
+    }
+}
+
+/// Decode a text frame body, which starts with its encoding
+fn decode_text<const N: usize>(data: &[u8], out: &mut String<N>) {
+    if let Some((&encoding, data)) = data.split_first() {
+        decode(encoding, split_terminated(encoding, data).0, out)
+    }
+}
+
+/// Decode ID3v2 text into `out`, truncating it when full
+fn decode<const N: usize>(encoding: u8, data: &[u8], out: &mut String<N>) {
+    out.clear();
+    let mut push = |c: char| out.push(c).is_ok();
+    match encoding {
+        // UTF-16 with byte order mark, or big endian without
+        1 | 2 => {
+            let (little_endian, data) = match data {
+                [0xFF, 0xFE, rest @ ..] => (true, rest),
+                [0xFE, 0xFF, rest @ ..] => (false, rest),
+                _ => (false, data),
+            };
+            let units = data.chunks_exact(2).map(|unit| match little_endian {
+                true => LittleEndian::read_u16(unit),
+                false => BigEndian::read_u16(unit),
+            });
+            for c in char::decode_utf16(units) {
+                if !push(c.unwrap_or(char::REPLACEMENT_CHARACTER)) {
+                    break;
+                }
+            }
+        }
+        3 => {
+            for c in data.utf8_chunks().flat_map(|chunk| chunk.valid().chars()) {
+                if !push(c) {
+                    break;
+                }
+            }
+        }
+        // ISO-8859-1
+        _ => {
+            for byte in data {
+                if !push(char::from(*byte)) {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Parse the APEv2 tag ending at `end`, returns where it starts
+fn read_ape<S: Source>(
+    source: &mut S,
+    start: u32,
+    end: u32,
+    tags: &mut Tags,
+) -> Result<u32, S::Error> {
+    let mut footer = [0u8; 32];
+    if start.checked_add(32).is_none_or(|min| end < min)
+        || read_at(source, end - 32, &mut footer)? < 32
+        || &footer[..8] != b"APETAGEX"
+    {
+        return Ok(end);
+    }
+
+    // Size includes the footer but not the optional header
+    let size = LittleEndian::read_u32(&footer[12..16]);
+    let items = LittleEndian::read_u32(&footer[16..20]);
+    let has_header = LittleEndian::read_u32(&footer[20..24]) & (1 << 31) != 0;
+    let tag_len = size.checked_add(if has_header { 32 } else { 0 });
+    if size < 32 || tag_len.is_none_or(|tag_len| end - start < tag_len) {
+        return Ok(end);
+    }
+    let items_end = end - 32;
+    let mut offset = end - size;
+
+    let mut item = [0u8; 8 + APE_KEY_LEN + 1];
+    let mut value = [0u8; BODY_LEN];
+    for _ in 0..items {
+        let read = read_at(source, offset, &mut item)?;
+        if read <= 8 {
+            break;
+        }
+        let Some(key_len) = item[8..read].iter().position(|byte| *byte == 0) else {
+            break;
+        };
+        let value_len = LittleEndian::read_u32(&item[..4]);
+        let value_offset = offset + 8 + key_len as u32 + 1;
+        match value_offset.checked_add(value_len) {
+            Some(next) if next <= items_end => offset = next,
+            _ => break,
+        }
+
+        // Only UTF-8 text items, binary items hold artwork
+        let is_text = (LittleEndian::read_u32(&item[4..8]) >> 1) & 3 == 0;
+        let Ok(key) = core::str::from_utf8(&item[8..8 + key_len]) else {
+            continue;
+        };
+        if !is_text || value_len as usize > BODY_LEN || !is_ape_key(key) {
+            continue;
+        }
+
+        let read = read_at(source, value_offset, &mut value[..value_len as usize])?;
+        let mut text = String::<MAX_TEXT>::new();
+        decode(3, &value[..read], &mut text);
+        match key {
+            key if key.eq_ignore_ascii_case("Title") => tags.set_title(&text),
+            key if key.eq_ignore_ascii_case("Artist") => tags.set_artist(&text),
+            key if key.eq_ignore_ascii_case("Album") => tags.set_album(&text),
+            key if key.eq_ignore_ascii_case("Track") => tags.set_track_number(&text),
+            key => tags.set_user_text(key, &text),
+        }
+    }
+
+    Ok(end - size - if has_header { 32 } else { 0 })
+}
+
+fn is_ape_key(key: &str) -> bool {
+    ["Title", "Artist", "Album", "Track"]
+        .iter()
+        .any(|known| key.eq_ignore_ascii_case(known))
+        || key
+            .get(..11)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("REPLAYGAIN_"))
+}
+
+/// Fill in anything still missing from an ID3v1(.1) tag
+fn read_id3v1(tag: &[u8; 128], tags: &mut Tags) {
+    let field = |range: Range<usize>| {
+        let mut text = String::<MAX_TEXT>::new();
+        let bytes = &tag[range];
+        let end = bytes
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(bytes.len());
+        decode(0, &bytes[..end], &mut text);
+        text
+    };
+
+    tags.set_title(field(3..33).trim_end());
+    tags.set_artist(field(33..63).trim_end());
+    tags.set_album(field(63..93).trim_end());
+    // ID3v1.1 stores the track number at the end of the comment
+    if tag[125] == 0 && tag[126] != 0 && tags.track_number.is_none() {
+        tags.track_number = Some(tag[126].into());
+    }
+}
+
+fn set_if_empty<const N: usize>(field: &mut String<N>, text: &str) {
+    if !field.is_empty() {
+        return;
+    }
+    for c in text.chars() {
+        if field.push(c).is_err() {
+            break;
+        }
+    }
+}
+
+/// Parse a ReplayGain value like `-6.20 dB` or `0.988547`
+fn parse_gain(text: &str) -> Option<f32> {
+    let text = text.trim();
+    let text = match text.len().checked_sub(2).and_then(|end| text.get(end..)) {
+        Some(unit) if unit.eq_ignore_ascii_case("dB") => &text[..text.len() - 2],
+        _ => text,
+    };
+    text.trim().parse().ok()
+}
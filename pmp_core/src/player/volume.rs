@@ -0,0 +1,131 @@
+//! Volume in dB steps and click-free gain changes
+
+use super::gain::db_to_linear;
+
+/// Lowest volume above silence
+pub const MIN_DB: f32 = -60.;
+pub const MAX_DB: f32 = 0.;
+/// Change per volume button press
+pub const STEP_DB: f32 = 2.;
+/// Time a gain change is spread over to avoid zipper noise
+pub const RAMP_MS: u32 = 5;
+
+/// Output volume, muting keeps the level to return to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Volume {
+    db: f32,
+    muted: bool,
+}
+
+impl Volume {
+    pub fn new(db: f32) -> Self {
+        Self {
+            db: db.clamp(MIN_DB, MAX_DB),
+            muted: false,
+        }
+    }
+
+    pub fn db(&self) -> f32 {
+        self.db
+    }
+
+    pub fn set_db(&mut self, db: f32) {
+        self.db = db.clamp(MIN_DB, MAX_DB);
+    }
+
+    /// Move by `steps` of [`STEP_DB`], negative steps turn it down
+    pub fn step(&mut self, steps: i32) {
+        self.set_db(self.db + steps as f32 * STEP_DB);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /// Linear gain, the bottom of the range is silent
+    pub fn gain(&self) -> f32 {
+        if self.muted || self.db <= MIN_DB {
+            0.
+        } else {
+            db_to_linear(self.db)
+        }
+    }
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self::new(-6.)
+    }
+}
+
+/// Linear gain ramp advanced once per frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ramp {
+    gain: f32,
+    target: f32,
+    step: f32,
+    /// Frames until the target is reached
+    left: u32,
+    /// Frames a full change takes
+    len: u32,
+}
+
+impl Ramp {
+    /// Start at `gain`, taking [`RAMP_MS`] to change at `sample_rate`
+    pub fn new(gain: f32, sample_rate: u32) -> Self {
+        Self::with_ms(gain, sample_rate, RAMP_MS)
+    }
+
+    /// Start at `gain`, taking `ms` to change at `sample_rate`
+    pub fn with_ms(gain: f32, sample_rate: u32, ms: u32) -> Self {
+        Self {
+            gain,
+            target: gain,
+            step: 0.,
+            left: 0,
+            len: (sample_rate * ms / 1000).max(1),
+        }
+    }
+
+    /// Current gain
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    /// Frames left until the target is reached
+    pub fn remaining(&self) -> usize {
+        self.left as usize
+    }
+
+    /// Head for `target` from wherever the ramp is now
+    pub fn set_target(&mut self, target: f32) {
+        if target == self.target {
+            return;
+        }
+        self.target = target;
+        self.left = self.len;
+        self.step = (target - self.gain) / self.len as f32;
+    }
+
+    /// Gain for the next frame
+    pub fn advance(&mut self) -> f32 {
+        if self.left > 0 {
+            self.left -= 1;
+            // Land exactly on the target rather than accumulating rounding errors
+            self.gain = if self.left == 0 {
+                self.target
+            } else {
+                self.gain + self.step
+            };
+        }
+        self.gain
+    }
+}
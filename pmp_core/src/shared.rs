@@ -0,0 +1,106 @@
+//! Large buffers shared by decoders that are never used at the same time
+
+use core::{
+    cell::UnsafeCell,
+    hint,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+/// Buffer borrowed in turn by any number of [`Share`]s, meant to be a static
+///
+/// There is no allocator, so a buffer kept by every open decoder would be paid for twice with
+/// a track queued for gapless playback. Instead each decoder takes a [`Share`] of one buffer and
+/// locks it for the duration of a call. The buffer remembers who used it last, so a decoder can
+/// tell whether its contents were overwritten by another one in the meantime and rebuild them.
+pub struct SharedBuffer<T> {
+    data: UnsafeCell<T>,
+    locked: AtomicBool,
+    /// Id of the share that last locked the buffer, 0 before the first lock
+    owner: AtomicU32,
+    /// Id of the next share
+    next_id: AtomicU32,
+}
+
+// Safety: the data is only reached through a guard, and only one guard exists at a time
+unsafe impl<T: Send> Sync for SharedBuffer<T> {}
+
+impl<T> SharedBuffer<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            data: UnsafeCell::new(data),
+            locked: AtomicBool::new(false),
+            owner: AtomicU32::new(0),
+            next_id: AtomicU32::new(1),
+        }
+    }
+
+    /// New user of the buffer, who does not hold its contents yet
+    pub fn share(&self) -> Share<'_, T> {
+        Share {
+            buffer: self,
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+/// One user of a [`SharedBuffer`]
+pub struct Share<'a, T> {
+    buffer: &'a SharedBuffer<T>,
+    id: u32,
+}
+
+impl<'a, T> Share<'a, T> {
+    /// Borrow the buffer until the guard is dropped
+    ///
+    /// Spins while another guard exists. Locks must not be nested or taken from different
+    /// interrupt priorities on a single core, as the spin would never end.
+    pub fn lock(&self) -> Guard<'a, T> {
+        let buffer = self.buffer;
+        while buffer
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+        let kept = buffer.owner.swap(self.id, Ordering::Relaxed) == self.id;
+        Guard { buffer, kept }
+    }
+}
+
+/// Exclusive borrow of a [`SharedBuffer`]
+pub struct Guard<'a, T> {
+    buffer: &'a SharedBuffer<T>,
+    kept: bool,
+}
+
+impl<T> Guard<'_, T> {
+    /// True if nobody else locked the buffer since this share last did, so it still holds what
+    /// was left in it
+    pub fn kept(&self) -> bool {
+        self.kept
+    }
+}
+
+impl<T> Deref for Guard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: the guard holds the lock
+        unsafe { &*self.buffer.data.get() }
+    }
+}
+
+impl<T> DerefMut for Guard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: the guard holds the lock
+        unsafe { &mut *self.buffer.data.get() }
+    }
+}
+
+impl<T> Drop for Guard<'_, T> {
+    fn drop(&mut self) {
+        self.buffer.locked.store(false, Ordering::Release);
+    }
+}
@@ -0,0 +1,44 @@
+//! Bookmark tests
+//!
+//! Run with `cargo test --test bookmarks_test`.
+
+#[cfg(test)]
+mod tests {
+    use pmp_core::player::{bookmarks::Bookmarks, position::PlaybackPosition};
+
+    fn at(millis: u64) -> PlaybackPosition {
+        PlaybackPosition::from_millis(millis, 44100)
+    }
+
+    #[test]
+    fn saves_and_replaces() {
+        let mut bookmarks: Bookmarks<&str, 4> = Bookmarks::new();
+        assert_eq!(bookmarks.get(&"a"), None);
+
+        bookmarks.save("a", at(1000));
+        bookmarks.save("b", at(2000));
+        bookmarks.save("a", at(3000));
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks.get(&"a"), Some(at(3000)));
+        assert_eq!(bookmarks.get(&"b"), Some(at(2000)));
+
+        assert_eq!(bookmarks.remove(&"a"), Some(at(3000)));
+        assert_eq!(bookmarks.get(&"a"), None);
+        assert_eq!(bookmarks.remove(&"a"), None);
+    }
+
+    #[test]
+    fn forgets_the_oldest_when_full() {
+        let mut bookmarks: Bookmarks<u8, 2> = Bookmarks::new();
+        bookmarks.save(1, at(1));
+        bookmarks.save(2, at(2));
+        // Saving again makes it the newest
+        bookmarks.save(1, at(3));
+        bookmarks.save(3, at(4));
+
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks.get(&2), None);
+        assert_eq!(bookmarks.get(&1), Some(at(3)));
+        assert_eq!(bookmarks.get(&3), Some(at(4)));
+    }
+}
@@ -0,0 +1,68 @@
+//! Channel mode and balance tests
+//!
+//! Run with `cargo test --test channels_test`.
+
+#[cfg(test)]
+mod tests {
+    use pmp_core::player::{
+        channels::{ChannelMixer, ChannelMode},
+        dsp::Processor,
+    };
+
+    const STEREO: [f32; 4] = [0.5, -0.25, 0.125, 1.];
+
+    fn mix(mixer: &mut ChannelMixer, channels: usize) -> [f32; 4] {
+        let mut pcm = STEREO;
+        mixer.process(&mut pcm, channels);
+        pcm
+    }
+
+    #[test]
+    fn passes_stereo_through() {
+        assert_eq!(mix(&mut ChannelMixer::default(), 2), STEREO);
+    }
+
+    #[test]
+    fn downmixes_to_mono() {
+        let mut mixer = ChannelMixer::new(ChannelMode::Mono);
+        assert_eq!(mix(&mut mixer, 2), [0.125, 0.125, 0.5625, 0.5625]);
+    }
+
+    #[test]
+    fn swaps_channels() {
+        let mut mixer = ChannelMixer::new(ChannelMode::Swap);
+        assert_eq!(mix(&mut mixer, 2), [-0.25, 0.5, 1., 0.125]);
+    }
+
+    #[test]
+    fn balances() {
+        let mut mixer = ChannelMixer::default();
+        mixer.set_balance(0.5);
+        assert_eq!(mixer.balance_gains(), (0.5, 1.));
+        assert_eq!(mix(&mut mixer, 2), [0.25, -0.25, 0.0625, 1.]);
+
+        mixer.set_balance(-3.);
+        assert_eq!(mixer.balance(), -1.);
+        assert_eq!(mix(&mut mixer, 2), [0.5, 0., 0.125, 0.]);
+
+        // Applied after the mode
+        mixer.set_mode(ChannelMode::Swap);
+        assert_eq!(mix(&mut mixer, 2), [-0.25, 0., 1., 0.]);
+    }
+
+    #[test]
+    fn leaves_mono_tracks() {
+        let mut mixer = ChannelMixer::new(ChannelMode::Swap);
+        mixer.set_balance(1.);
+        assert_eq!(mix(&mut mixer, 1), STEREO);
+    }
+
+    #[test]
+    fn cycles_modes() {
+        let mut mode = ChannelMode::default();
+        for expected in ["Mono", "Swap", "Stereo"] {
+            mode = mode.cycle();
+            assert_eq!(mode.name(), expected);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! Circular buffer bookkeeping tests
+//!
+//! Run with `cargo test --test circular_test`.
+
+#[cfg(test)]
+mod tests {
+    use pmp_core::player::circular::{free_ranges, Underruns};
+
+    #[test]
+    fn splits_free_part_where_it_wraps() {
+        assert_eq!(free_ranges(100, 10, 50), [10..60, 0..0]);
+        assert_eq!(free_ranges(100, 60, 40), [60..100, 0..0]);
+        assert_eq!(free_ranges(100, 80, 50), [80..100, 0..30]);
+        // Never more than the whole buffer
+        assert_eq!(free_ranges(100, 30, 250), [30..100, 0..30]);
+        assert_eq!(free_ranges(100, 0, 0), [0..0, 0..0]);
+    }
+
+    #[test]
+    fn zeroing_both_ranges_clears_all_free_bytes() {
+        let mut buffer = [1u8; 64];
+        // Written up to 48, played up to 16
+        for range in free_ranges(buffer.len(), 48, 32) {
+            buffer[range].fill(0);
+        }
+        assert!(buffer[..16].iter().all(|&byte| byte == 0));
+        assert!(buffer[16..48].iter().all(|&byte| byte == 1));
+        assert!(buffer[48..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn counts_each_underrun_once() {
+        let mut underruns = Underruns::default();
+        underruns.written();
+        assert_eq!(underruns.count(), 0);
+
+        // Retries while still behind are the same underrun
+        underruns.late();
+        underruns.late();
+        underruns.late();
+        assert!(underruns.is_late());
+        assert_eq!(underruns.count(), 1);
+
+        underruns.written();
+        assert!(!underruns.is_late());
+        underruns.late();
+        underruns.written();
+        underruns.written();
+        assert_eq!(underruns.count(), 2);
+    }
+}
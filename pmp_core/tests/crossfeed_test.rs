@@ -0,0 +1,110 @@
+//! Crossfeed tests
+//!
+//! Run with `cargo test --test crossfeed_test`.
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::PI;
+
+    use pmp_core::player::{
+        crossfeed::{Crossfeed, CrossfeedLevel},
+        dsp::Processor,
+    };
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    /// Steady state peak levels of a sine at `frequency` hard panned left, in the left and right
+    /// output
+    fn panned_sine(crossfeed: &mut Crossfeed, frequency: f32) -> (f32, f32) {
+        let mut pcm = [0f32; 2 * 8820];
+        for (n, frame) in pcm.chunks_exact_mut(2).enumerate() {
+            frame[0] = 0.5 * libm::sinf(2. * PI * frequency * n as f32 / SAMPLE_RATE as f32);
+        }
+        crossfeed.process(&mut pcm, 2);
+        pcm[pcm.len() / 2..]
+            .chunks_exact(2)
+            .fold((0f32, 0f32), |(left, right), frame| {
+                (left.max(frame[0].abs()), right.max(frame[1].abs()))
+            })
+    }
+
+    #[test]
+    fn feeds_bass_at_the_preset_level() {
+        for (level, feed_db) in [
+            (CrossfeedLevel::Low, 9.5),
+            (CrossfeedLevel::Medium, 6.),
+            (CrossfeedLevel::High, 4.5),
+        ] {
+            let mut crossfeed = Crossfeed::new(level, SAMPLE_RATE);
+            // Constant signal on the left only
+            let mut pcm = [0f32; 2 * 4410];
+            for frame in pcm.chunks_exact_mut(2) {
+                frame[0] = 0.5;
+            }
+            crossfeed.process(&mut pcm, 2);
+
+            let (left, right) = (pcm[pcm.len() - 2], pcm[pcm.len() - 1]);
+            assert!((left + right - 0.5).abs() < 1e-3, "{left} + {right}");
+            let db = 20. * libm::log10f(right / left);
+            assert!((db + feed_db).abs() < 0.05, "{level:?} {db}");
+        }
+    }
+
+    #[test]
+    fn feeds_less_treble() {
+        let mut crossfeed = Crossfeed::new(CrossfeedLevel::High, SAMPLE_RATE);
+        let (left, right) = panned_sine(&mut crossfeed, 100.);
+        let bass = right / left;
+        crossfeed.reset();
+        let (left, right) = panned_sine(&mut crossfeed, 8000.);
+        let treble = right / left;
+        assert!(treble < bass / 4., "{bass} {treble}");
+    }
+
+    #[test]
+    fn stronger_levels_feed_more() {
+        let mut last = 0.;
+        for level in [
+            CrossfeedLevel::Low,
+            CrossfeedLevel::Medium,
+            CrossfeedLevel::High,
+        ] {
+            let (left, right) = panned_sine(&mut Crossfeed::new(level, SAMPLE_RATE), 300.);
+            assert!(right / left > last);
+            last = right / left;
+        }
+    }
+
+    #[test]
+    fn bypasses_mono_and_off() {
+        let mut crossfeed = Crossfeed::new(CrossfeedLevel::High, SAMPLE_RATE);
+        let mut pcm = [0.5, -0.25, 0.125];
+        crossfeed.process(&mut pcm, 1);
+        assert_eq!(pcm, [0.5, -0.25, 0.125]);
+
+        let mut crossfeed = Crossfeed::new(CrossfeedLevel::Off, SAMPLE_RATE);
+        let mut pcm = [0.5, 0., 0.5, 0.];
+        crossfeed.process(&mut pcm, 2);
+        assert_eq!(pcm, [0.5, 0., 0.5, 0.]);
+    }
+
+    #[test]
+    fn keeps_centre_untouched() {
+        let mut crossfeed = Crossfeed::new(CrossfeedLevel::Medium, SAMPLE_RATE);
+        crossfeed.configure(48000, 2);
+        let mut pcm = [0f32; 2 * 4800];
+        pcm.fill(0.3);
+        crossfeed.process(&mut pcm, 2);
+        assert!((pcm[pcm.len() - 1] - 0.3).abs() < 1e-3);
+        assert_eq!(pcm[pcm.len() - 1], pcm[pcm.len() - 2]);
+    }
+
+    #[test]
+    fn cycles_levels() {
+        let mut level = CrossfeedLevel::default();
+        for expected in ["Low", "Medium", "High", "Off"] {
+            level = level.cycle();
+            assert_eq!(level.name(), expected);
+        }
+    }
+}
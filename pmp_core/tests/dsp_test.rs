@@ -0,0 +1,155 @@
+//! Effect chain tests with synthetic processors
+//!
+//! Run with `cargo test --test dsp_test`.
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use pmp_core::player::{
+        dsp::{Chain, Cost, Effect, Processor},
+        eq::{Equalizer, Preset},
+    };
+
+    /// Scales every sample and records how it was driven
+    #[derive(Debug, Default)]
+    struct Scale {
+        factor: f32,
+        sample_rate: u32,
+        channels: usize,
+        resets: u32,
+    }
+
+    impl Scale {
+        fn new(factor: f32) -> Self {
+            Self {
+                factor,
+                ..Self::default()
+            }
+        }
+    }
+
+    impl Processor for Scale {
+        fn configure(&mut self, sample_rate: u32, channels: usize) {
+            self.sample_rate = sample_rate;
+            self.channels = channels;
+        }
+
+        fn process(&mut self, pcm: &mut [f32], _channels: usize) {
+            for sample in pcm {
+                *sample *= self.factor;
+            }
+        }
+
+        fn reset(&mut self) {
+            self.resets += 1;
+        }
+    }
+
+    #[test]
+    fn runs_stages_in_order() {
+        let mut chain: Chain<Scale, 4> = Chain::new();
+        chain.push(Scale::new(2.)).unwrap();
+        chain.push(Scale::new(-0.5)).unwrap();
+        chain.configure(44100, 2);
+
+        let mut pcm = [0.25, -0.5, 1., 0.];
+        chain.process(&mut pcm, &|| 0);
+        assert_eq!(pcm, [-0.25, 0.5, -1., 0.]);
+        assert!(chain.stages().iter().all(|stage| {
+            stage.processor().sample_rate == 44100 && stage.processor().channels == 2
+        }));
+    }
+
+    #[test]
+    fn bypasses_disabled_stages() {
+        let mut chain: Chain<Scale, 4> = Chain::new();
+        chain.push(Scale::new(3.)).unwrap();
+        let index = chain.push(Scale::new(10.)).unwrap();
+        chain.configure(48000, 1);
+
+        chain.stage_mut(index).unwrap().set_enabled(false);
+        let mut pcm = [0.1];
+        chain.process(&mut pcm, &|| 0);
+        assert!((pcm[0] - 0.3).abs() < 1e-6);
+
+        // Re-enabling clears the stage
+        let stage = chain.stage_mut(index).unwrap();
+        stage.set_enabled(true);
+        stage.set_enabled(true);
+        assert!(stage.is_enabled());
+        assert_eq!(stage.processor().resets, 1);
+    }
+
+    #[test]
+    fn is_fixed_capacity() {
+        let mut chain: Chain<Scale, 2> = Chain::new();
+        assert_eq!(chain.push(Scale::new(1.)).ok(), Some(0));
+        assert_eq!(chain.push(Scale::new(1.)).ok(), Some(1));
+        let rejected = chain.push(Scale::new(7.)).unwrap_err();
+        assert_eq!(rejected.factor, 7.);
+        assert_eq!(chain.stages().len(), 2);
+    }
+
+    #[test]
+    fn configures_late_stages() {
+        let mut chain: Chain<Scale, 2> = Chain::new();
+        chain.configure(32000, 2);
+        chain.push(Scale::new(1.)).unwrap();
+        assert_eq!(chain.stages()[0].processor().sample_rate, 32000);
+    }
+
+    #[test]
+    fn measures_cost() {
+        let mut chain: Chain<Scale, 4> = Chain::new();
+        chain.push(Scale::new(1.)).unwrap();
+        let index = chain.push(Scale::new(1.)).unwrap();
+        chain.configure(1000, 2);
+
+        // Every reading advances the clock by 5 ticks
+        let time = Cell::new(0);
+        let clock = || {
+            time.set(time.get() + 5);
+            time.get()
+        };
+        let mut pcm = [0f32; 200];
+        chain.process(&mut pcm, &clock);
+        chain.process(&mut pcm, &clock);
+
+        let stage = chain.stages()[index].cost();
+        assert_eq!(
+            stage,
+            Cost {
+                ticks: 10,
+                frames: 200
+            }
+        );
+        assert_eq!(
+            chain.cost(),
+            Cost {
+                ticks: 20,
+                frames: 200
+            }
+        );
+        // 20 ticks at 100 Hz for 200 ms of audio
+        assert_eq!(chain.cost().load(1000, 100), 1.);
+
+        chain.stage_mut(index).unwrap().set_enabled(false);
+        assert_eq!(chain.cost().ticks, 10);
+        chain.clear_cost();
+        assert_eq!(chain.cost(), Cost::default());
+        assert_eq!(chain.cost().load(1000, 100), 0.);
+    }
+
+    #[test]
+    fn dispatches_effects() {
+        let mut chain: Chain<Effect, 2> = Chain::new();
+        let equalizer = Equalizer::new(Preset::FLAT, 44100);
+        assert!(chain.push(Effect::Equalizer(equalizer)).is_ok());
+        chain.configure(44100, 2);
+
+        let mut pcm = [0.5, -0.5];
+        chain.process(&mut pcm, &|| 0);
+        assert_eq!(pcm, [0.5, -0.5]);
+    }
+}
@@ -0,0 +1,134 @@
+//! Equalizer frequency response tests
+//!
+//! Run with `cargo test --test eq_test`.
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::PI;
+
+    use pmp_core::player::{
+        dsp::Processor,
+        eq::{Band, Coefficients, Equalizer, FilterKind, Preset, BANDS},
+    };
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn assert_db(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{actual} dB is not {expected} dB"
+        );
+    }
+
+    /// Peak amplitude of a sine at `frequency` after the equalizer has settled
+    fn sine_gain(eq: &mut Equalizer, frequency: f32, sample_rate: u32) -> f32 {
+        let mut pcm = [0f32; 4800];
+        for (n, sample) in pcm.iter_mut().enumerate() {
+            *sample = 0.25 * libm::sinf(2. * PI * frequency * n as f32 / sample_rate as f32);
+        }
+        eq.process(&mut pcm, 1);
+        let peak = pcm[2400..]
+            .iter()
+            .fold(0f32, |peak, sample| peak.max(sample.abs()));
+        20. * libm::log10f(peak / 0.25)
+    }
+
+    #[test]
+    fn flat_passes_through() {
+        let mut eq = Equalizer::new(Preset::FLAT, SAMPLE_RATE);
+        assert!(eq
+            .coefficients()
+            .iter()
+            .all(|coefficients| *coefficients == Coefficients::IDENTITY));
+
+        let mut pcm = [0.5, -0.25, 0.125, 1.];
+        eq.process(&mut pcm, 2);
+        assert_eq!(pcm, [0.5, -0.25, 0.125, 1.]);
+    }
+
+    #[test]
+    fn peak_matches_bandwidth_curve() {
+        let band = Band::new(FilterKind::Peak, 1000., 1.).with_gain(6.);
+        let coefficients = Coefficients::new(&band, SAMPLE_RATE);
+        assert_db(coefficients.response(1000., SAMPLE_RATE), 6., 0.01);
+
+        // Q sets the bandwidth in octaves between the points of half the gain
+        let octaves = 2. / core::f32::consts::LN_2 * libm::asinhf(1. / (2. * band.q));
+        let edge = libm::powf(2., octaves / 2.);
+        assert_db(coefficients.response(1000. / edge, SAMPLE_RATE), 3., 0.1);
+        assert_db(coefficients.response(1000. * edge, SAMPLE_RATE), 3., 0.1);
+
+        // Far from the centre
+        assert_db(coefficients.response(20., SAMPLE_RATE), 0., 0.05);
+        assert_db(coefficients.response(20000., SAMPLE_RATE), 0., 0.1);
+    }
+
+    #[test]
+    fn shelves_boost_one_side() {
+        let low = Coefficients::new(
+            &Band::new(FilterKind::LowShelf, 100., 0.707).with_gain(-8.),
+            SAMPLE_RATE,
+        );
+        assert_db(low.response(10., SAMPLE_RATE), -8., 0.1);
+        assert_db(low.response(100., SAMPLE_RATE), -4., 0.05);
+        assert_db(low.response(5000., SAMPLE_RATE), 0., 0.05);
+
+        let high = Coefficients::new(
+            &Band::new(FilterKind::HighShelf, 8000., 0.707).with_gain(5.),
+            SAMPLE_RATE,
+        );
+        assert_db(high.response(22000., SAMPLE_RATE), 5., 0.2);
+        assert_db(high.response(8000., SAMPLE_RATE), 2.5, 0.05);
+        assert_db(high.response(200., SAMPLE_RATE), 0., 0.05);
+    }
+
+    #[test]
+    fn filters_sines() {
+        let mut preset = Preset::FLAT;
+        preset.bands[3].gain_db = 9.;
+        let mut eq = Equalizer::new(preset, SAMPLE_RATE);
+        assert_db(sine_gain(&mut eq, 1000., SAMPLE_RATE), 9., 0.1);
+        eq.reset();
+        assert_db(sine_gain(&mut eq, 60., SAMPLE_RATE), 0., 0.2);
+    }
+
+    #[test]
+    fn recomputes_for_sample_rate() {
+        let mut preset = Preset::FLAT;
+        preset.bands[3].gain_db = -6.;
+        let mut eq = Equalizer::new(preset, SAMPLE_RATE);
+        let before = eq.coefficients()[3];
+
+        eq.set_sample_rate(22050);
+        assert_ne!(eq.coefficients()[3], before);
+        assert_db(eq.coefficients()[3].response(1000., 22050), -6., 0.01);
+        assert_db(sine_gain(&mut eq, 1000., 22050), -6., 0.1);
+    }
+
+    #[test]
+    fn edits_presets() {
+        let mut eq = Equalizer::new(Preset::BASS_BOOST, SAMPLE_RATE);
+        assert_db(eq.coefficients()[0].response(20., SAMPLE_RATE), 6., 0.2);
+
+        // Tweak a band and keep the result as a new preset
+        eq.set_band(
+            BANDS - 1,
+            Band::new(FilterKind::HighShelf, 10000., 0.707).with_gain(3.),
+        );
+        let custom = *eq.preset();
+        assert_eq!(custom.bands[0], Preset::BASS_BOOST.bands[0]);
+        assert_eq!(custom.bands[BANDS - 1].gain_db, 3.);
+
+        eq.set_preset(Preset::FLAT);
+        eq.set_preset(custom);
+        assert_db(
+            eq.coefficients()[BANDS - 1].response(10000., SAMPLE_RATE),
+            1.5,
+            0.05,
+        );
+
+        // Past the last band nothing changes
+        eq.set_band(BANDS, Band::new(FilterKind::Peak, 1000., 1.).with_gain(6.));
+        assert_eq!(*eq.preset(), custom);
+    }
+}
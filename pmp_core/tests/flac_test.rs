@@ -0,0 +1,184 @@
+//! FLAC decoder tests comparing against reference pcm
+//!
+//! Run with `cargo test --test flac_test`.
+
+#[cfg(test)]
+mod tests {
+    use pmp_core::{
+        codec::{
+            flac::{FlacDecoder, SeekPoint},
+            AudioDecoder, Codec, Error, Format, MAX_SAMPLES,
+        },
+        fs::SliceSource,
+    };
+
+    /// 44.1 kHz 16-bit stereo in fixed 1152 sample blocks, covering every subframe type and
+    /// channel decorrelation mode, with a seek table
+    const STEREO16: &[u8] = include_bytes!("fixtures/stereo16.flac");
+    const STEREO16_PCM: &[u8] = include_bytes!("fixtures/stereo16.pcm");
+    /// 48 kHz 24-bit mono in variable size blocks, with wasted bits and no seek table
+    const MONO24: &[u8] = include_bytes!("fixtures/mono24.flac");
+    const MONO24_PCM: &[u8] = include_bytes!("fixtures/mono24.pcm");
+
+    fn stereo16(sample: usize) -> f32 {
+        let bytes = &STEREO16_PCM[sample * 2..];
+        f32::from(i16::from_le_bytes([bytes[0], bytes[1]])) / 32768.
+    }
+
+    fn mono24(sample: usize) -> f32 {
+        let bytes = &MONO24_PCM[sample * 3..];
+        (i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8) as f32 / 8388608.
+    }
+
+    /// Decode to the end, comparing against `reference` from interleaved sample `start`, returns
+    /// the number of samples decoded
+    fn compare(
+        codec: &mut Codec,
+        source: &mut SliceSource,
+        reference: fn(usize) -> f32,
+        start: usize,
+    ) -> usize {
+        let mut pcm = [0f32; MAX_SAMPLES];
+        let mut decoded = 0;
+        while let Some(info) = codec.decode(source, &mut pcm).unwrap() {
+            for &sample in &pcm[..info.frames * info.channels] {
+                assert_eq!(
+                    sample,
+                    reference(start + decoded),
+                    "sample {}",
+                    start + decoded
+                );
+                decoded += 1;
+            }
+        }
+        decoded
+    }
+
+    #[test]
+    fn reads_metadata() {
+        assert_eq!(Format::detect(STEREO16), Some(Format::Flac));
+
+        let mut source = SliceSource::new(STEREO16);
+        let flac = FlacDecoder::open(&mut source, 0..STEREO16.len() as u32).unwrap();
+        let info = flac.info();
+        assert_eq!(info.max_block_size, 1152);
+        assert_eq!(info.sample_rate, 44100);
+        assert_eq!(info.channels, 2);
+        assert_eq!(info.bits_per_sample, 16);
+        assert_eq!(info.total_samples, 10000);
+
+        // Placeholder point dropped
+        assert_eq!(flac.seek_table().len(), 3);
+        assert_eq!(
+            flac.seek_table()[0],
+            SeekPoint {
+                sample: 0,
+                offset: 0
+            }
+        );
+        assert_eq!(flac.seek_table()[1].sample, 3 * 1152);
+    }
+
+    #[test]
+    fn decodes_16_bit_stereo() {
+        let mut source = SliceSource::new(STEREO16);
+        let mut codec = Codec::open(&mut source, 0..STEREO16.len() as u32).unwrap();
+        assert_eq!(codec.format(), Format::Flac);
+        assert_eq!(codec.length(), Some(10000));
+
+        let decoded = compare(&mut codec, &mut source, stereo16, 0);
+        assert_eq!(decoded, STEREO16_PCM.len() / 2);
+    }
+
+    #[test]
+    fn decodes_24_bit_mono() {
+        let mut source = SliceSource::new(MONO24);
+        let mut codec = Codec::open(&mut source, 0..MONO24.len() as u32).unwrap();
+        assert_eq!(codec.sample_rate(), 48000);
+        assert_eq!(codec.channels(), 1);
+
+        let decoded = compare(&mut codec, &mut source, mono24, 0);
+        assert_eq!(decoded, MONO24_PCM.len() / 3);
+    }
+
+    #[test]
+    fn seeks_with_seek_table() {
+        let mut source = SliceSource::new(STEREO16);
+        let mut codec = Codec::open(&mut source, 0..STEREO16.len() as u32).unwrap();
+
+        for target in [5000, 1152 * 6, 0, 9999] {
+            assert_eq!(codec.seek(&mut source, target), Ok(target));
+            let decoded = compare(&mut codec, &mut source, stereo16, target as usize * 2);
+            assert_eq!(decoded, (10000 - target as usize) * 2);
+        }
+        assert_eq!(codec.seek(&mut source, 20000), Ok(10000));
+    }
+
+    #[test]
+    fn seeks_by_bisection() {
+        let mut source = SliceSource::new(MONO24);
+        let mut codec = Codec::open(&mut source, 0..MONO24.len() as u32).unwrap();
+        let total = MONO24_PCM.len() / 3;
+
+        for target in [9000, 1000, 6000, 12000, 42] {
+            assert_eq!(codec.seek(&mut source, target as u64), Ok(target as u64));
+            assert_eq!(
+                compare(&mut codec, &mut source, mono24, target),
+                total - target
+            );
+        }
+    }
+
+    #[test]
+    fn interleaves_decoders_sharing_the_block() {
+        let mut stereo_source = SliceSource::new(STEREO16);
+        let mut stereo = Codec::open(&mut stereo_source, 0..STEREO16.len() as u32).unwrap();
+        let mut mono_source = SliceSource::new(MONO24);
+        let mut mono = Codec::open(&mut mono_source, 0..MONO24.len() as u32).unwrap();
+
+        // Short reads leave each block half played when the other decoder takes over
+        let mut pcm = [0f32; 300];
+        let (mut stereo_decoded, mut mono_decoded) = (0, 0);
+        let mut done = (false, false);
+        while done != (true, true) {
+            match stereo.decode(&mut stereo_source, &mut pcm).unwrap() {
+                Some(info) => {
+                    for &sample in &pcm[..info.frames * info.channels] {
+                        assert_eq!(sample, stereo16(stereo_decoded), "stereo {stereo_decoded}");
+                        stereo_decoded += 1;
+                    }
+                }
+                None => done.0 = true,
+            }
+            match mono.decode(&mut mono_source, &mut pcm).unwrap() {
+                Some(info) => {
+                    for &sample in &pcm[..info.frames] {
+                        assert_eq!(sample, mono24(mono_decoded), "mono {mono_decoded}");
+                        mono_decoded += 1;
+                    }
+                }
+                None => done.1 = true,
+            }
+        }
+        assert_eq!(stereo_decoded, STEREO16_PCM.len() / 2);
+        assert_eq!(mono_decoded, MONO24_PCM.len() / 3);
+    }
+
+    #[test]
+    fn rejects_corrupt_frames() {
+        let mut data = [0u8; STEREO16.len()];
+        data.copy_from_slice(STEREO16);
+        data[STEREO16.len() - 200] ^= 0x10;
+
+        let mut source = SliceSource::new(&data);
+        let mut codec = Codec::open(&mut source, 0..data.len() as u32).unwrap();
+        let mut pcm = [0f32; MAX_SAMPLES];
+        let result = loop {
+            match codec.decode(&mut source, &mut pcm) {
+                Ok(Some(_)) => continue,
+                result => break result,
+            }
+        };
+        assert_eq!(result, Err(Error::Malformed));
+    }
+}
@@ -0,0 +1,88 @@
+//! ReplayGain normalisation tests
+//!
+//! Run with `cargo test --test gain_test`.
+
+#[cfg(test)]
+mod tests {
+    use pmp_core::player::{
+        gain::{db_to_linear, Normalizer, ReplayGainMode},
+        tags::ReplayGain,
+    };
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{actual} is not {expected}"
+        );
+    }
+
+    const TAGGED: ReplayGain = ReplayGain {
+        track_gain: Some(-6.),
+        track_peak: Some(0.9),
+        album_gain: Some(-3.),
+        album_peak: Some(0.95),
+    };
+
+    #[test]
+    fn converts_db() {
+        assert_close(db_to_linear(0.), 1.);
+        assert_close(db_to_linear(-20.), 0.1);
+        assert_close(db_to_linear(6.0206), 2.);
+    }
+
+    #[test]
+    fn selects_values_by_mode() {
+        assert_eq!(ReplayGainMode::Off.gain(&TAGGED), 1.);
+        assert_close(ReplayGainMode::Track.gain(&TAGGED), db_to_linear(-6.));
+        assert_close(ReplayGainMode::Album.gain(&TAGGED), db_to_linear(-3.));
+        assert_eq!(ReplayGainMode::Track.gain(&ReplayGain::default()), 1.);
+    }
+
+    #[test]
+    fn falls_back_to_other_values() {
+        let track_only = ReplayGain {
+            album_gain: None,
+            album_peak: None,
+            ..TAGGED
+        };
+        assert_close(ReplayGainMode::Album.gain(&track_only), db_to_linear(-6.));
+
+        let album_only = ReplayGain {
+            track_gain: None,
+            track_peak: None,
+            ..TAGGED
+        };
+        assert_close(ReplayGainMode::Track.gain(&album_only), db_to_linear(-3.));
+    }
+
+    #[test]
+    fn prevents_clipping() {
+        let quiet = ReplayGain {
+            track_gain: Some(10.),
+            track_peak: Some(0.5),
+            ..ReplayGain::default()
+        };
+        assert_close(ReplayGainMode::Track.gain(&quiet), 2.);
+
+        // No peak to go by
+        let unknown_peak = ReplayGain {
+            track_peak: None,
+            ..quiet
+        };
+        assert_close(ReplayGainMode::Track.gain(&unknown_peak), db_to_linear(10.));
+    }
+
+    #[test]
+    fn follows_track_and_mode_changes() {
+        let mut normalizer = Normalizer::default();
+        normalizer.set_values(TAGGED);
+        assert_eq!(normalizer.gain(), 1.);
+
+        normalizer.set_mode(ReplayGainMode::Album);
+        assert_close(normalizer.gain(), db_to_linear(-3.));
+
+        normalizer.set_values(ReplayGain::default());
+        assert_eq!(normalizer.gain(), 1.);
+        assert_eq!(normalizer.mode(), ReplayGainMode::Album);
+    }
+}
@@ -0,0 +1,110 @@
+//! Look-ahead limiter tests
+//!
+//! Run with `cargo test --test limiter_test`.
+
+#[cfg(test)]
+mod tests {
+    use pmp_core::player::{
+        dsp::Processor,
+        limiter::{Limiter, DEFAULT_THRESHOLD},
+    };
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    #[test]
+    fn passes_quiet_audio_delayed() {
+        let mut limiter = Limiter::new(SAMPLE_RATE);
+        let latency = limiter.latency();
+        assert!(latency > 0 && latency < 128);
+
+        let mut pcm = [0f32; 400];
+        for (i, sample) in pcm.iter_mut().enumerate() {
+            *sample = (i as f32 / 400.) - 0.5;
+        }
+        let input = pcm;
+        limiter.process(&mut pcm, 2);
+
+        assert!(pcm[..latency * 2].iter().all(|&sample| sample == 0.));
+        assert_eq!(pcm[latency * 2..], input[..400 - latency * 2]);
+        assert_eq!(limiter.limited_samples(), 0);
+    }
+
+    #[test]
+    fn keeps_peaks_below_threshold() {
+        let mut limiter = Limiter::new(SAMPLE_RATE);
+        let mut pcm = [0f32; 4000];
+        for (i, sample) in pcm.iter_mut().enumerate() {
+            // Loud square wave with occasional spikes
+            *sample = if (i / 50) % 2 == 0 { 1.5 } else { -1.2 };
+            if i % 777 == 0 {
+                *sample = 4.;
+            }
+        }
+        limiter.process(&mut pcm, 2);
+
+        let peak = pcm.iter().fold(0f32, |peak, sample| peak.max(sample.abs()));
+        assert!(peak <= DEFAULT_THRESHOLD + 1e-5, "{peak}");
+        assert!(limiter.limited_samples() > 3000);
+    }
+
+    #[test]
+    fn ramps_down_ahead_of_a_peak() {
+        let mut limiter = Limiter::new(SAMPLE_RATE);
+        let latency = limiter.latency();
+        let spike = 500;
+        let mut pcm = [0.5f32; 1000];
+        pcm[spike] = 2.;
+        limiter.process(&mut pcm, 1);
+
+        // Output is delayed, so the spike comes out `latency` frames later
+        let out = spike + latency;
+        assert!(pcm[out].abs() <= DEFAULT_THRESHOLD + 1e-5);
+        assert_eq!(pcm[out - latency - 1], 0.5);
+        // The gain falls steadily instead of jumping
+        let mut last = 1.;
+        for sample in &pcm[out - latency..out] {
+            let gain = sample / 0.5;
+            assert!(gain <= last && last - gain < 0.05, "{last} {gain}");
+            last = gain;
+        }
+    }
+
+    #[test]
+    fn releases_gradually() {
+        let mut limiter = Limiter::new(SAMPLE_RATE);
+        let latency = limiter.latency();
+        let mut pcm = [0.5f32; 4000];
+        pcm[100] = 4.;
+        limiter.process(&mut pcm, 1);
+
+        // Still recovering a millisecond after the peak
+        let after = 100 + latency + 60;
+        assert!(pcm[after] < 0.45, "{}", pcm[after]);
+        assert!(pcm[after + 1] >= pcm[after]);
+
+        // Back to unity within a second, after which nothing more is counted
+        let mut pcm = [0.5f32; 4000];
+        for _ in 0..10 {
+            pcm.fill(0.5);
+            limiter.process(&mut pcm, 1);
+        }
+        assert_eq!(pcm[3999], 0.5);
+        let limited = limiter.limited_samples();
+        limiter.process(&mut pcm, 1);
+        assert_eq!(limiter.limited_samples(), limited);
+    }
+
+    #[test]
+    fn resets_on_channel_change() {
+        let mut limiter = Limiter::new(SAMPLE_RATE).with_threshold(0.5);
+        let mut pcm = [1f32; 200];
+        limiter.process(&mut pcm, 2);
+        assert!(limiter.limited_samples() > 0);
+
+        // Mono audio starts with an empty delay line
+        let mut pcm = [0.25f32; 200];
+        limiter.process(&mut pcm, 1);
+        assert_eq!(pcm[0], 0.);
+        assert_eq!(pcm[199], 0.25);
+    }
+}
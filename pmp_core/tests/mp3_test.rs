@@ -0,0 +1,313 @@
+//! MP3 stream header parsing tests
+//!
+//! Run with `cargo test --test mp3_test`.
+
+#[cfg(test)]
+mod tests {
+    use pmp_core::{
+        codec::{
+            mp3::{self, Checkpoints, FrameHeader, SeekPoint, StreamInfo, Trim, Version},
+            AudioDecoder, Codec, MAX_SAMPLES,
+        },
+        fs::{ReadAhead, SliceSource},
+        shared::SharedBuffer,
+    };
+
+    /// LAME VBR at 44.1 kHz mono, a sweep with bursts of noise, so most frames take their data
+    /// from the bit reservoir of the frames before
+    const VBR_MONO: &[u8] = include_bytes!("fixtures/vbr_mono.mp3");
+
+    /// MPEG1 Layer III, 128 kbit/s, 44.1 kHz, joint stereo
+    const HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x44];
+    const FRAME_LEN: usize = 417;
+
+    /// Build an Info frame for `frames` audio frames with a LAME tag, followed by an audio frame
+    fn info_frame(frames: u32, delay: u16, padding: u16) -> [u8; FRAME_LEN * 2] {
+        let mut buf = [0u8; FRAME_LEN * 2];
+        buf[..4].copy_from_slice(&HEADER);
+        buf[FRAME_LEN..FRAME_LEN + 4].copy_from_slice(&HEADER);
+
+        let xing = &mut buf[36..];
+        xing[..4].copy_from_slice(b"Info");
+        xing[4..8].copy_from_slice(&0x0Fu32.to_be_bytes());
+        xing[8..12].copy_from_slice(&frames.to_be_bytes());
+        xing[12..16].copy_from_slice(&(frames * FRAME_LEN as u32).to_be_bytes());
+        for (i, entry) in xing[16..116].iter_mut().enumerate() {
+            *entry = (i * 256 / 100) as u8;
+        }
+        let lame = &mut xing[120..];
+        lame[..9].copy_from_slice(b"LAME3.100");
+        lame[21] = (delay >> 4) as u8;
+        lame[22] = ((delay & 0xF) << 4) as u8 | (padding >> 8) as u8;
+        lame[23] = padding as u8;
+        buf
+    }
+
+    /// Build a stream of `frames` silent audio frames after an Info frame with a LAME tag
+    fn silent_stream(frames: u32, delay: u16, padding: u16) -> Vec<u8> {
+        let mut stream = info_frame(frames, delay, padding)[..FRAME_LEN].to_vec();
+        for _ in 0..frames {
+            let start = stream.len();
+            stream.resize(start + FRAME_LEN, 0);
+            stream[start..start + 4].copy_from_slice(&HEADER);
+        }
+        stream
+    }
+
+    /// Decode to the end, returns the samples per channel of each block
+    fn decode_blocks(codec: &mut Codec, source: &mut SliceSource) -> Vec<usize> {
+        let mut pcm = [0f32; MAX_SAMPLES];
+        let mut blocks = Vec::new();
+        while let Some(info) = codec.decode(source, &mut pcm).unwrap() {
+            assert_eq!(info.channels, 2);
+            blocks.push(info.frames);
+        }
+        blocks
+    }
+
+    /// Decode to the end, returns the samples
+    fn decode_all(codec: &mut Codec, source: &mut SliceSource) -> Vec<f32> {
+        let mut pcm = [0f32; MAX_SAMPLES];
+        let mut samples = Vec::new();
+        while let Some(info) = codec.decode(source, &mut pcm).unwrap() {
+            samples.extend_from_slice(&pcm[..info.frames * info.channels]);
+        }
+        samples
+    }
+
+    #[test]
+    fn parses_frame_header() {
+        let header = FrameHeader::parse(&HEADER).unwrap();
+        assert_eq!(header.version, Version::Mpeg1);
+        assert_eq!(header.bitrate, 128);
+        assert_eq!(header.sample_rate, 44100);
+        assert_eq!(header.channels, 2);
+        assert_eq!(header.samples_per_frame(), 1152);
+        assert_eq!(header.frame_len(), FRAME_LEN as u32);
+
+        assert_eq!(FrameHeader::parse(&[0xFF, 0xFB, 0xF0, 0x44]), None);
+        assert_eq!(FrameHeader::parse(&[0x49, 0x44, 0x33, 0x04]), None);
+    }
+
+    #[test]
+    fn reads_gapless_info() {
+        let buf = info_frame(100, 576, 1000);
+        let stream = StreamInfo::parse(&buf, 20).unwrap();
+        assert_eq!(stream.first_frame, 20);
+        assert_eq!(stream.audio_start, 20 + FRAME_LEN as u32);
+
+        let xing = stream.xing.as_ref().unwrap();
+        assert_eq!(xing.frames, Some(100));
+        assert_eq!(xing.toc.unwrap()[50], 128);
+
+        assert_eq!(
+            stream.trim(),
+            Some(Trim {
+                skip: 576 + mp3::DECODER_DELAY,
+                length: 100 * 1152 - 576 - 1000,
+            })
+        );
+    }
+
+    #[test]
+    fn skips_leading_garbage() {
+        let mut buf = [0u8; FRAME_LEN * 2 + 7];
+        buf[..7].copy_from_slice(&[0xFF, 0xFF, 0x00, 0xFF, 0xFB, 0x00, 0x12]);
+        buf[7..].copy_from_slice(&info_frame(10, 0, 0));
+        assert_eq!(mp3::find_frame(&buf).map(|(position, _)| position), Some(7));
+    }
+
+    #[test]
+    fn counts_samples() {
+        let buf = info_frame(100, 576, 1000);
+        let stream = StreamInfo::parse(&buf, 0).unwrap();
+        assert_eq!(stream.samples(0), 100 * 1152 - 576 - 1000);
+
+        // Constant bitrate estimate from the size of the audio
+        let stream = StreamInfo::parse(&HEADER, 0).unwrap();
+        assert_eq!(stream.samples(16000), 16000 * 8 * 44100 / 128_000);
+    }
+
+    #[test]
+    fn trims_delay_and_padding_when_decoding() {
+        let stream = silent_stream(100, 576, 1000);
+        let mut source = SliceSource::new(&stream);
+        let mut codec = Codec::open(&mut source, 0..stream.len() as u32).unwrap();
+        let length = 100 * 1152 - 576 - 1000;
+        assert_eq!(codec.length(), Some(length));
+
+        let blocks = decode_blocks(&mut codec, &mut source);
+        // The encoder and decoder delay are dropped from the first frame
+        assert_eq!(blocks[0], 1152 - 576 - mp3::DECODER_DELAY as usize);
+        // The padding is dropped from the last frames, so the next track splices on right
+        // after the last sample of this one
+        assert_eq!(blocks.iter().sum::<usize>() as u64, length);
+
+        // Seeking lands on the exact sample of the trimmed stream
+        assert_eq!(codec.seek(&mut source, 50_000), Ok(50_000));
+        let blocks = decode_blocks(&mut codec, &mut source);
+        assert_eq!(blocks.iter().sum::<usize>() as u64, length - 50_000);
+    }
+
+    #[test]
+    fn seeks_to_the_exact_sample_past_the_bit_reservoir() {
+        let info_len = FrameHeader::parse(VBR_MONO).unwrap().frame_len() as usize;
+        // Without the Info frame nothing is trimmed
+        for stream in [VBR_MONO, &VBR_MONO[info_len..]] {
+            let mut source = SliceSource::new(stream);
+            let mut codec = Codec::open(&mut source, 0..stream.len() as u32).unwrap();
+            let whole = decode_all(&mut codec, &mut source);
+
+            for target in [20_000, 1000, 33_333, 0, 60_000] {
+                assert_eq!(codec.seek(&mut source, target), Ok(target));
+                let samples = decode_all(&mut codec, &mut source);
+                assert_eq!(samples.len(), whole.len() - target as usize);
+                let error = samples
+                    .iter()
+                    .zip(&whole[target as usize..])
+                    .map(|(a, b)| (a - b).abs())
+                    .fold(0., f32::max);
+                assert!(error < 1e-4, "{error} off at {target}");
+            }
+        }
+    }
+
+    #[test]
+    fn estimates_frames_from_xing_toc() {
+        let stream = StreamInfo::parse(VBR_MONO, 0).unwrap();
+        let frames = stream.xing.as_ref().unwrap().frames.unwrap();
+        let mut source = SliceSource::new(VBR_MONO);
+        let checkpoints = &mut Checkpoints::default();
+
+        for frame in 0..frames {
+            let point = stream.lookup(frame).unwrap();
+            // Close to the end the offset can be past the start of the last frame
+            let Some(offset) = mp3::resync(&mut source, point.offset).unwrap() else {
+                continue;
+            };
+            let estimate = stream.toc_frame(offset).unwrap();
+            // The frame actually at the offset
+            let mut actual = 0;
+            while mp3::scan(&mut source, stream.audio_start, 0, actual, checkpoints)
+                .unwrap()
+                .0
+                < offset
+            {
+                actual += 1;
+            }
+            assert!(estimate.abs_diff(actual) <= 3, "{estimate} for {actual}");
+        }
+    }
+
+    #[test]
+    fn records_checkpoints_when_decoding() {
+        let interval = mp3::CHECKPOINT_INTERVAL;
+        let stream = silent_stream(2 * interval + 10, 0, 0);
+        let mut source = SliceSource::new(&stream);
+        let mut codec = Codec::open(&mut source, 0..stream.len() as u32).unwrap();
+        decode_blocks(&mut codec, &mut source);
+
+        let Codec::Mp3(decoder) = &codec else {
+            panic!("Not an MP3 stream");
+        };
+        assert_eq!(
+            decoder.checkpoints().before(2 * interval + 5),
+            Some(SeekPoint {
+                offset: (2 * interval + 1) * FRAME_LEN as u32,
+                frame: 2 * interval,
+            })
+        );
+    }
+
+    #[test]
+    fn looks_up_xing_toc() {
+        let buf = info_frame(100, 576, 1000);
+        let stream = StreamInfo::parse(&buf, 0).unwrap();
+        assert_eq!(
+            stream.lookup(50),
+            Some(SeekPoint {
+                offset: 100 * FRAME_LEN as u32 / 2,
+                frame: 50,
+            })
+        );
+        // Never points into the Info frame
+        assert_eq!(stream.lookup(0).unwrap().offset, FRAME_LEN as u32);
+    }
+
+    #[test]
+    fn scans_frame_headers() {
+        let mut buf = [0u8; FRAME_LEN * 8 + 5];
+        for frame in 0..8 {
+            // Junk between the fourth and fifth frame
+            let offset = frame * FRAME_LEN + if frame >= 4 { 5 } else { 0 };
+            buf[offset..offset + 4].copy_from_slice(&HEADER);
+        }
+        let mut source = SliceSource::new(&buf);
+        let checkpoints = &mut Checkpoints::default();
+
+        assert_eq!(
+            mp3::scan(&mut source, 0, 0, 3, checkpoints),
+            Ok((3 * FRAME_LEN as u32, 3))
+        );
+        assert_eq!(
+            mp3::scan(&mut source, 0, 0, 6, checkpoints),
+            Ok((6 * FRAME_LEN as u32 + 5, 6))
+        );
+        // Stops at the end of the stream
+        assert_eq!(mp3::scan(&mut source, 0, 0, 100, checkpoints).unwrap().1, 8);
+        assert_eq!(
+            mp3::resync(&mut source, FRAME_LEN as u32 * 4 + 1),
+            Ok(Some(FRAME_LEN as u32 * 4 + 5))
+        );
+    }
+
+    #[test]
+    fn scans_from_checkpoints() {
+        static BUF: SharedBuffer<[u8; 4096]> = SharedBuffer::new([0; 4096]);
+        let interval = mp3::CHECKPOINT_INTERVAL;
+        let stream = silent_stream(3 * interval, 0, 0);
+        let mut source = ReadAhead::new(SliceSource::new(&stream[FRAME_LEN..]), &BUF);
+        let mut checkpoints = Checkpoints::default();
+
+        let target = 2 * interval + 100;
+        let reached = mp3::scan(&mut source, 0, 0, target, &mut checkpoints).unwrap();
+        assert_eq!(reached, (target * FRAME_LEN as u32, target));
+        // Several headers come out of each read
+        let reads = source.stats().reads;
+        assert!(reads < u64::from(target) / 2, "{reads} reads");
+
+        assert_eq!(checkpoints.len(), 2);
+        assert_eq!(checkpoints.before(interval - 1), None);
+        let from = checkpoints.before(target).unwrap();
+        assert_eq!(
+            from,
+            SeekPoint {
+                offset: 2 * interval * FRAME_LEN as u32,
+                frame: 2 * interval,
+            }
+        );
+
+        // Scanning again only covers the frames after the checkpoint
+        let reached = mp3::scan(
+            &mut source,
+            from.offset,
+            from.frame,
+            target,
+            &mut checkpoints,
+        );
+        assert_eq!(reached, Ok((target * FRAME_LEN as u32, target)));
+        assert!(source.stats().reads - reads < 100);
+        assert_eq!(checkpoints.len(), 2);
+
+        // Carries on recording past the last checkpoint
+        mp3::scan(
+            &mut source,
+            from.offset,
+            from.frame,
+            3 * interval,
+            &mut checkpoints,
+        )
+        .unwrap();
+        assert_eq!(checkpoints.len(), 3);
+    }
+}
@@ -0,0 +1,75 @@
+//! Sample format conversion tests
+//!
+//! Run with `cargo test --test pcm_test`.
+
+#[cfg(test)]
+mod tests {
+    use pmp_core::player::pcm::{quantize, Quantizer, Tpdf};
+
+    #[test]
+    fn quantize_known_values() {
+        assert_eq!(quantize(0.0, 0.0), 0);
+        assert_eq!(quantize(0.5, 0.0), 16384);
+        assert_eq!(quantize(-0.5, 0.0), -16384);
+        assert_eq!(quantize(-1.0, 0.0), i16::MIN);
+        assert_eq!(quantize(1.0 / 32768.0, 0.0), 1);
+    }
+
+    #[test]
+    fn quantize_clips() {
+        assert_eq!(quantize(1.0, 0.0), i16::MAX);
+        assert_eq!(quantize(3.5, 0.0), i16::MAX);
+        assert_eq!(quantize(-3.5, 0.0), i16::MIN);
+        assert_eq!(quantize(f32::NAN, 0.0), 0);
+    }
+
+    #[test]
+    fn writes_little_endian_interleaved() {
+        let mut out = [0u8; 8];
+        let written = Quantizer::new().write(&[0.5, -0.5, 0.25, -1.0], 1.0, &mut out);
+        assert_eq!(written, 8);
+        assert_eq!(out, [0x00, 0x40, 0x00, 0xC0, 0x00, 0x20, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn applies_gain_before_quantisation() {
+        let mut out = [0u8; 4];
+        Quantizer::new().write(&[1.0, -1.0], 0.5, &mut out);
+        assert_eq!(out, [0x00, 0x40, 0x00, 0xC0]);
+    }
+
+    #[test]
+    fn writes_mono_to_both_channels() {
+        let mut out = [0u8; 10];
+        let written = Quantizer::new().write_mono(&[0.5, -1.0, 0.25], 1.0, &mut out);
+        // Room for two stereo frames only
+        assert_eq!(written, 8);
+        assert_eq!(out[..8], [0x00, 0x40, 0x00, 0x40, 0x00, 0x80, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn write_is_limited_by_output() {
+        let mut out = [0u8; 3];
+        assert_eq!(Quantizer::new().write(&[0.1, 0.2], 1.0, &mut out), 2);
+    }
+
+    #[test]
+    fn dither_stays_within_one_lsb() {
+        let mut tpdf = Tpdf::default();
+        let mut sum = 0.0;
+        for _ in 0..10_000 {
+            let value = tpdf.sample();
+            assert!(value > -1.0 && value < 1.0);
+            sum += value;
+        }
+        // Zero mean
+        assert!((sum / 10_000.0).abs() < 0.02);
+
+        let mut quantizer = Quantizer::new().with_dither(Tpdf::default());
+        let mut out = [0u8; 2];
+        for _ in 0..1000 {
+            quantizer.write(&[0.0], 1.0, &mut out);
+            assert!(i16::from_le_bytes(out).abs() <= 1);
+        }
+    }
+}
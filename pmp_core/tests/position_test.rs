@@ -0,0 +1,67 @@
+//! Playback position tests
+//!
+//! Run with `cargo test --test position_test`.
+
+#[cfg(test)]
+mod tests {
+    use core::{fmt::Write, time::Duration};
+    use heapless::String;
+    use pmp_core::player::position::PlaybackPosition;
+
+    fn format(position: PlaybackPosition) -> String<16> {
+        let mut text = String::new();
+        write!(text, "{position}").unwrap();
+        text
+    }
+
+    #[test]
+    fn converts_to_time() {
+        let position = PlaybackPosition::new(66150, 44100);
+        assert_eq!(position.as_millis(), 1500);
+        assert_eq!(position.as_duration(), Duration::from_millis(1500));
+        assert_eq!(PlaybackPosition::from_millis(1500, 44100), position);
+        assert_eq!(
+            PlaybackPosition::from_duration(Duration::from_millis(1500), 44100),
+            position
+        );
+    }
+
+    #[test]
+    fn counts_exactly() {
+        // An hour in blocks of 1152 frames, a multiple of neither rate
+        let mut position = PlaybackPosition::new(0, 48000);
+        for _ in 0..48000 * 3600 / 1152 {
+            position.advance(1152);
+        }
+        assert_eq!(position.samples(), 48000 * 3600);
+        assert_eq!(position.as_duration(), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn converts_between_rates() {
+        let position = PlaybackPosition::new(48000 * 90, 48000);
+        assert_eq!(
+            position.at_rate(44100),
+            PlaybackPosition::new(44100 * 90, 44100)
+        );
+        assert_eq!(position.at_rate(44100).as_millis(), position.as_millis());
+
+        let earlier = PlaybackPosition::from_millis(30_000, 44100);
+        assert_eq!(position.saturating_sub(earlier).as_millis(), 60_000);
+        assert_eq!(earlier.saturating_sub(position).samples(), 0);
+    }
+
+    #[test]
+    fn formats_minutes_and_seconds() {
+        assert_eq!(format(PlaybackPosition::new(0, 44100)), "00:00");
+        assert_eq!(
+            format(PlaybackPosition::from_millis(187_999, 44100)),
+            "03:07"
+        );
+        assert_eq!(
+            format(PlaybackPosition::from_millis(6_000_000, 44100)),
+            "100:00"
+        );
+        assert_eq!(format(PlaybackPosition::default()), "00:00");
+    }
+}
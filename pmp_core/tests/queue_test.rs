@@ -0,0 +1,193 @@
+//! Play queue tests
+//!
+//! Run with `cargo test --test queue_test`.
+
+#[cfg(test)]
+mod tests {
+    use pmp_core::player::{
+        queue::{Queue, RepeatMode},
+        rng::XorShift,
+    };
+
+    fn queue(repeat: RepeatMode) -> Queue<u8, 8> {
+        let mut queue = Queue::from_items([1, 2, 3]);
+        queue.set_repeat(repeat);
+        queue
+    }
+
+    #[test]
+    fn plays_in_order_then_stops() {
+        let mut queue = queue(RepeatMode::Off);
+        assert_eq!(queue.current(), None);
+        assert_eq!(queue.next(), Some(&1));
+        assert_eq!(queue.next(), Some(&2));
+        assert_eq!(queue.advance(), Some(&3));
+        assert_eq!(queue.peek_advance(), None);
+        assert_eq!(queue.advance(), None);
+        assert_eq!(queue.current_index(), None);
+    }
+
+    #[test]
+    fn repeat_all_wraps_both_ways() {
+        let mut queue = queue(RepeatMode::All);
+        queue.jump(2);
+        assert_eq!(queue.peek_advance(), Some(&1));
+        assert_eq!(queue.next(), Some(&1));
+        assert_eq!(queue.previous(), Some(&3));
+        assert_eq!(queue.previous(), Some(&2));
+    }
+
+    #[test]
+    fn repeat_one_only_repeats_at_the_end_of_a_track() {
+        let mut queue = queue(RepeatMode::One);
+        queue.jump(1);
+        assert_eq!(queue.peek_advance(), Some(&2));
+        assert_eq!(queue.advance(), Some(&2));
+        // Skipping still moves on, and wraps around
+        assert_eq!(queue.next(), Some(&3));
+        assert_eq!(queue.next(), Some(&1));
+    }
+
+    #[test]
+    fn previous_stays_on_first() {
+        let mut queue = queue(RepeatMode::Off);
+        queue.next();
+        assert_eq!(queue.previous(), Some(&1));
+        assert_eq!(queue.current_index(), Some(0));
+    }
+
+    #[test]
+    fn jumps_within_bounds() {
+        let mut queue = queue(RepeatMode::Off);
+        assert_eq!(queue.jump(1), Some(&2));
+        assert_eq!(queue.jump(3), None);
+        assert_eq!(queue.current(), Some(&2));
+    }
+
+    #[test]
+    fn play_next_and_append() {
+        let mut queue = queue(RepeatMode::Off);
+        queue.play_next(0).unwrap();
+        assert_eq!(queue.items(), &[0, 1, 2, 3]);
+
+        queue.jump(1);
+        queue.play_next(9).unwrap();
+        queue.append(4).unwrap();
+        assert_eq!(queue.items(), &[0, 1, 9, 2, 3, 4]);
+        assert_eq!(queue.next(), Some(&9));
+    }
+
+    #[test]
+    fn rejects_items_when_full() {
+        let mut queue: Queue<u8, 8> = Queue::from_items(0..10);
+        assert_eq!(queue.len(), 8);
+        assert_eq!(queue.append(8), Err(8));
+        assert_eq!(queue.play_next(9), Err(9));
+    }
+
+    #[test]
+    fn removes_items_moving_to_the_following() {
+        let mut queue = queue(RepeatMode::Off);
+        queue.jump(1);
+        assert_eq!(queue.remove(0), Some(1));
+        assert_eq!(queue.current(), Some(&2));
+        assert_eq!(queue.remove(0), Some(2));
+        assert_eq!(queue.current(), Some(&3));
+        assert_eq!(queue.remove(1), None);
+        assert_eq!(queue.remove(0), Some(3));
+        assert_eq!(queue.current(), None);
+    }
+
+    #[test]
+    fn removing_the_last_item_wraps_with_repeat() {
+        let mut queue = queue(RepeatMode::All);
+        queue.jump(2);
+        assert_eq!(queue.upcoming(), Some(0));
+        assert_eq!(queue.remove(2), Some(3));
+        assert_eq!(queue.current(), Some(&1));
+    }
+
+    #[test]
+    fn unshuffle_restores_order_after_removing() {
+        let mut queue: Queue<u8, 16> = Queue::from_items(0..8);
+        queue.shuffle(&mut XorShift::new(3));
+        let index = queue.items().iter().position(|&item| item == 4).unwrap();
+        queue.remove(index);
+        queue.unshuffle();
+        assert_eq!(queue.items(), &[0, 1, 2, 3, 5, 6, 7]);
+    }
+
+    fn sorted<const N: usize>(items: &[u8]) -> [u8; N] {
+        let mut sorted: [u8; N] = items.try_into().unwrap();
+        sorted.sort_unstable();
+        sorted
+    }
+
+    #[test]
+    fn shuffles_every_item_once_keeping_current_first() {
+        let mut queue: Queue<u8, 32> = Queue::from_items(0..32);
+        queue.jump(10);
+        queue.shuffle(&mut XorShift::new(1));
+
+        assert!(queue.is_shuffled());
+        assert_eq!(queue.current_index(), Some(0));
+        assert_eq!(queue.current(), Some(&10));
+        assert_eq!(
+            sorted::<32>(queue.items()),
+            core::array::from_fn(|i| i as u8)
+        );
+        assert_ne!(queue.items()[1..11], [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn same_seed_same_order() {
+        let mut a: Queue<u8, 16> = Queue::from_items(0..16);
+        let mut b = a.clone();
+        a.shuffle(&mut XorShift::new(7));
+        b.shuffle(&mut XorShift::new(7));
+        assert_eq!(a.items(), b.items());
+
+        b.unshuffle();
+        b.shuffle(&mut XorShift::new(8));
+        assert_ne!(a.items(), b.items());
+    }
+
+    #[test]
+    fn unshuffle_restores_order_with_added_items() {
+        let mut queue: Queue<u8, 16> = Queue::from_items(0..8);
+        queue.jump(3);
+        queue.shuffle(&mut XorShift::new(3));
+        queue.append(8).unwrap();
+        // Follows the current item in both orders
+        queue.play_next(30).unwrap();
+        queue.next();
+
+        queue.unshuffle();
+        assert!(!queue.is_shuffled());
+        assert_eq!(queue.items(), &[0, 1, 2, 3, 30, 4, 5, 6, 7, 8]);
+        assert_eq!(queue.current(), Some(&30));
+    }
+
+    #[test]
+    fn shuffles_albums_keeping_track_order() {
+        // Tens are the album, units the track number
+        let tracks = [10, 11, 12, 20, 21, 30, 40, 41, 42, 43, 50, 51];
+        let mut queue: Queue<u8, 16> = Queue::from_items(tracks);
+        queue.jump(4);
+        queue.shuffle_albums(&mut XorShift::new(5), |track| track / 10);
+
+        let items = queue.items();
+        assert_eq!(&items[..2], &[20, 21]);
+        assert_eq!(queue.current(), Some(&21));
+        assert_eq!(sorted::<12>(items), tracks);
+        for pair in items.windows(2) {
+            // Within an album the next track follows, otherwise a new album starts
+            assert!(pair[1] == pair[0] + 1 || pair[1] % 10 == 0, "{items:?}");
+        }
+        assert_ne!(items, &tracks);
+
+        queue.unshuffle();
+        assert_eq!(queue.items(), &tracks);
+        assert_eq!(queue.current(), Some(&21));
+    }
+}
@@ -0,0 +1,174 @@
+//! Read-ahead buffer tests
+//!
+//! Run with `cargo test --test read_ahead_test`.
+
+#[cfg(test)]
+mod tests {
+    use pmp_core::{
+        codec::{AudioDecoder, Codec, MAX_SAMPLES},
+        fs::{ReadAhead, ReadRate, ReadStats, SliceSource, Source, SD_BLOCK},
+        shared::SharedBuffer,
+    };
+
+    const STEREO16: &[u8] = include_bytes!("fixtures/stereo16.flac");
+
+    fn data() -> [u8; 16384] {
+        core::array::from_fn(|i| (i * 7 + i / 256) as u8)
+    }
+
+    #[test]
+    fn reads_in_bulk() {
+        static BUF: SharedBuffer<[u8; 4096]> = SharedBuffer::new([0; 4096]);
+        let data = data();
+        let mut source = ReadAhead::new(SliceSource::new(&data), &BUF);
+        let mut buf = [0u8; 128];
+        for chunk in data.chunks(128) {
+            assert_eq!(source.read(&mut buf).unwrap(), 128);
+            assert_eq!(&buf[..], chunk);
+        }
+        assert_eq!(source.read(&mut buf).unwrap(), 0);
+
+        let stats = source.stats();
+        assert_eq!(stats.reads, 129);
+        // One more to find the end
+        assert_eq!(stats.source_reads, 5);
+        assert_eq!(stats.source_bytes, data.len() as u64);
+    }
+
+    #[test]
+    fn aligns_refills_to_blocks() {
+        static BUF: SharedBuffer<[u8; 1024]> = SharedBuffer::new([0; 1024]);
+        let data = data();
+        let mut source = ReadAhead::new(SliceSource::new(&data), &BUF);
+        source.seek_from_start(1000).unwrap();
+        let mut buf = [0u8; 600];
+        assert_eq!(source.read(&mut buf[..100]).unwrap(), 100);
+        assert_eq!(&buf[..100], &data[1000..1100]);
+        assert_eq!(source.source().offset(), SD_BLOCK as u32 + 1024);
+
+        // The rest of the buffer, then a refill on the next block
+        assert_eq!(source.read(&mut buf).unwrap(), 436);
+        assert_eq!(&buf[..436], &data[1100..1536]);
+        assert_eq!(source.read(&mut buf).unwrap(), 600);
+        assert_eq!(&buf[..], &data[1536..2136]);
+        assert_eq!(source.source().offset(), 2560);
+        assert_eq!(source.stats().source_reads, 2);
+    }
+
+    #[test]
+    fn seeks_within_buffer() {
+        static BUF: SharedBuffer<[u8; 2048]> = SharedBuffer::new([0; 2048]);
+        let data = data();
+        let mut source = ReadAhead::new(SliceSource::new(&data), &BUF);
+        let mut buf = [0u8; 64];
+        source.read(&mut buf).unwrap();
+
+        source.seek_from_start(1500).unwrap();
+        source.read(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[1500..1564]);
+        source.seek_from_start(10).unwrap();
+        source.read(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[10..74]);
+        assert_eq!(source.stats().source_reads, 1);
+
+        source.seek_from_start(9000).unwrap();
+        assert_eq!(source.offset(), 9000);
+        source.read(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[9000..9064]);
+        assert_eq!(source.stats().source_reads, 2);
+    }
+
+    #[test]
+    fn reads_large_buffers_directly() {
+        static BUF: SharedBuffer<[u8; 1024]> = SharedBuffer::new([0; 1024]);
+        let data = data();
+        let mut source = ReadAhead::new(SliceSource::new(&data), &BUF);
+        let mut buf = [0u8; 3000];
+        source.seek_from_start(100).unwrap();
+        assert_eq!(source.read_full(&mut buf).unwrap(), 3000);
+        assert_eq!(&buf[..], &data[100..3100]);
+        assert_eq!(source.buffered(), 0);
+        assert_eq!(source.stats().source_bytes, 3000);
+
+        let mut small = [0u8; 16];
+        source.read(&mut small).unwrap();
+        assert_eq!(&small[..], &data[3100..3116]);
+    }
+
+    #[test]
+    fn shares_the_buffer() {
+        static BUF: SharedBuffer<[u8; 1024]> = SharedBuffer::new([0; 1024]);
+        let data = data();
+        let mut first = ReadAhead::new(SliceSource::new(&data), &BUF);
+        let mut second = ReadAhead::new(SliceSource::new(&data[8192..]), &BUF);
+        let mut buf = [0u8; 100];
+
+        // Each read after the other source refilled the buffer refills it again
+        for i in 0..3 {
+            first.read(&mut buf).unwrap();
+            assert_eq!(&buf[..], &data[i * 100..(i + 1) * 100]);
+            second.read(&mut buf).unwrap();
+            assert_eq!(&buf[..], &data[8192 + i * 100..8192 + (i + 1) * 100]);
+        }
+        assert_eq!(first.stats().source_reads, 3);
+        assert_eq!(second.stats().source_reads, 3);
+
+        // Left alone, the buffer is kept
+        for i in 3..6 {
+            first.read(&mut buf).unwrap();
+            assert_eq!(&buf[..], &data[i * 100..(i + 1) * 100]);
+        }
+        assert_eq!(first.stats().source_reads, 4);
+    }
+
+    #[test]
+    fn measures_read_rate() {
+        let stats = ReadStats {
+            reads: 3200,
+            source_reads: 100,
+            source_bytes: 409600,
+        };
+        let rate = stats.rate(2000);
+        assert_eq!(
+            rate,
+            ReadRate {
+                unbuffered: 1600,
+                buffered: 50,
+                bytes: 204800,
+            }
+        );
+        assert_eq!(rate.reduction(), 32);
+        assert_eq!(
+            format!("{rate}"),
+            "1600 reads/s unbuffered, 50 reads/s buffered (204800 B/s), 32x fewer"
+        );
+        assert_eq!(ReadStats::default().rate(0).reduction(), 0);
+    }
+
+    #[test]
+    fn decodes_the_same() {
+        static BUF: SharedBuffer<[u8; 4096]> = SharedBuffer::new([0; 4096]);
+        let len = STEREO16.len() as u32;
+        let mut direct = SliceSource::new(STEREO16);
+        let mut codec = Codec::open(&mut direct, 0..len).unwrap();
+        let mut buffered = ReadAhead::new(SliceSource::new(STEREO16), &BUF);
+        let mut buffered_codec = Codec::open(&mut buffered, 0..len).unwrap();
+
+        let mut pcm = [0f32; MAX_SAMPLES];
+        let mut buffered_pcm = [0f32; MAX_SAMPLES];
+        while let Some(info) = codec.decode(&mut direct, &mut pcm).unwrap() {
+            let buffered_info = buffered_codec
+                .decode(&mut buffered, &mut buffered_pcm)
+                .unwrap()
+                .unwrap();
+            assert_eq!(info, buffered_info);
+            let n = info.frames * info.channels;
+            assert_eq!(pcm[..n], buffered_pcm[..n]);
+        }
+        assert!(buffered_codec
+            .decode(&mut buffered, &mut buffered_pcm)
+            .unwrap()
+            .is_none());
+        assert!(buffered.stats().source_reads < buffered.stats().reads);
+    }
+}
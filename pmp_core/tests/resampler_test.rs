@@ -0,0 +1,98 @@
+//! Resampler tests using generated sine tracks
+//!
+//! Run with `cargo test --test resampler_test`.
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::PI;
+    use pmp_core::player::resampler::Resampler;
+
+    const SECONDS: usize = 1;
+
+    /// Resample a generated stereo sine and return its measured frequency and peak amplitude
+    fn measure(freq: f32, from: u32, to: u32) -> (f32, f32) {
+        let mut resampler = Resampler::new(to);
+        resampler.configure(from, 2);
+
+        let mut input = [0f32; 1152 * 2];
+        let mut output = [0f32; 1152 * 2];
+        let mut frame = 0usize;
+        let mut crossings = 0usize;
+        let mut produced_frames = 0usize;
+        let mut previous = 0f32;
+        let mut peak = 0f32;
+
+        while frame < from as usize * SECONDS {
+            for chunk in input.chunks_exact_mut(2) {
+                let value = libm::sinf(2. * PI * freq * frame as f32 / from as f32);
+                chunk.fill(value);
+                frame += 1;
+            }
+
+            let mut remaining = &input[..];
+            while !remaining.is_empty() {
+                let (consumed, produced) = resampler.process(remaining, &mut output);
+                remaining = &remaining[consumed..];
+                for chunk in output[..produced].chunks_exact(2) {
+                    assert_eq!(chunk[0], chunk[1]);
+                    // Skip the filter warm up
+                    if produced_frames > 64 {
+                        if previous < 0. && chunk[0] >= 0. {
+                            crossings += 1;
+                        }
+                        peak = peak.max(chunk[0].abs());
+                    }
+                    previous = chunk[0];
+                    produced_frames += 1;
+                }
+            }
+        }
+
+        let seconds = produced_frames as f32 / to as f32;
+        (crossings as f32 / seconds, peak)
+    }
+
+    fn assert_pitch(freq: f32, from: u32, to: u32) {
+        let (measured, peak) = measure(freq, from, to);
+        assert!(
+            (measured - freq).abs() < freq * 0.01,
+            "{from} -> {to}: expected {freq} Hz, measured {measured} Hz"
+        );
+        assert!((peak - 1.).abs() < 0.05, "{from} -> {to}: peak {peak}");
+    }
+
+    #[test]
+    fn preserves_pitch_downsampling() {
+        assert_pitch(1000., 48000, 44100);
+    }
+
+    #[test]
+    fn preserves_pitch_upsampling() {
+        assert_pitch(1000., 32000, 44100);
+        assert_pitch(440., 22050, 44100);
+    }
+
+    #[test]
+    fn output_length_follows_ratio() {
+        let mut resampler = Resampler::new(44100);
+        resampler.configure(22050, 1);
+        let input = [0f32; 1000];
+        let mut output = [0f32; 4000];
+        let (consumed, produced) = resampler.process(&input, &mut output);
+        assert_eq!(consumed, 1000);
+        assert!((2000..=2002).contains(&produced));
+    }
+
+    #[test]
+    fn passthrough_at_same_rate() {
+        let mut resampler = Resampler::new(44100);
+        resampler.configure(44100, 2);
+        assert!(resampler.is_passthrough());
+        let mut output = [0f32; 4];
+        assert_eq!(
+            resampler.process(&[0.1, 0.2, 0.3, 0.4], &mut output),
+            (4, 4)
+        );
+        assert_eq!(output, [0.1, 0.2, 0.3, 0.4]);
+    }
+}
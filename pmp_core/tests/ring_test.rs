@@ -0,0 +1,96 @@
+//! PCM ring buffer tests
+//!
+//! Run with `cargo test --test ring_test`.
+
+#[cfg(test)]
+mod tests {
+    use pmp_core::player::ring::Ring;
+
+    fn ramp<const N: usize>(start: usize) -> [f32; N] {
+        core::array::from_fn(|i| (start + i) as f32)
+    }
+
+    #[test]
+    fn splits_once() {
+        let ring = Ring::<8>::new();
+        assert!(ring.split().is_some());
+        assert!(ring.split().is_none());
+    }
+
+    #[test]
+    fn wraps_around_in_order() {
+        let ring = Ring::<8>::new();
+        let (mut producer, mut consumer) = ring.split().unwrap();
+        let mut out = [0f32; 6];
+
+        // Each round moves the indices on by 6, so the copies wrap at different points
+        for round in 0..5 {
+            let start = round * 6;
+            assert_eq!(producer.write(&ramp::<6>(start)), 6);
+            assert_eq!(consumer.read(&mut out), 6);
+            assert_eq!(out, ramp::<6>(start));
+        }
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn stops_writing_when_full() {
+        let ring = Ring::<8>::new();
+        let (mut producer, mut consumer) = ring.split().unwrap();
+
+        assert_eq!(producer.write(&ramp::<6>(0)), 6);
+        assert_eq!(producer.write(&ramp::<6>(6)), 2);
+        assert_eq!(producer.free(), 0);
+
+        let mut out = [0f32; 8];
+        assert_eq!(consumer.read(&mut out), 8);
+        assert_eq!(out, ramp::<8>(0));
+    }
+
+    #[test]
+    fn waits_for_watermark_after_running_dry() {
+        let ring = Ring::<16>::new();
+        let (mut producer, mut consumer) = ring.split().unwrap();
+        consumer.set_watermark(8);
+        let mut out = [0f32; 4];
+
+        producer.write(&ramp::<6>(0));
+        assert_eq!(consumer.read(&mut out), 0);
+        producer.write(&ramp::<2>(6));
+        assert_eq!(consumer.read(&mut out), 4);
+
+        // Once started the ring is read down to empty
+        assert_eq!(consumer.read(&mut out), 4);
+        assert_eq!(out, ramp::<4>(4));
+
+        producer.write(&ramp::<4>(8));
+        assert_eq!(consumer.read(&mut out), 0);
+        producer.write(&ramp::<4>(12));
+        assert_eq!(consumer.read(&mut out), 4);
+        assert_eq!(out, ramp::<4>(8));
+    }
+
+    #[test]
+    fn flush_drops_audio_written_before() {
+        let ring = Ring::<16>::new();
+        let (mut producer, mut consumer) = ring.split().unwrap();
+        let mut out = [0f32; 8];
+
+        producer.write(&ramp::<6>(0));
+        producer.flush();
+        producer.write(&ramp::<4>(100));
+        assert_eq!(consumer.flush_pending(), Some(6));
+
+        // Reading stops at the flush point
+        assert_eq!(consumer.read(&mut out[..4]), 4);
+        assert_eq!(consumer.read(&mut out), 2);
+        assert_eq!(consumer.flush_pending(), None);
+
+        producer.flush();
+        consumer.flush();
+        assert!(consumer.is_empty());
+        producer.write(&ramp::<2>(200));
+        assert_eq!(consumer.read(&mut out), 2);
+        assert_eq!(out[..2], ramp::<2>(200));
+    }
+}
@@ -0,0 +1,52 @@
+//! Shared buffer tests
+//!
+//! Run with `cargo test --test shared_test`.
+
+#[cfg(test)]
+mod tests {
+    use pmp_core::shared::SharedBuffer;
+
+    #[test]
+    fn tracks_who_used_the_buffer_last() {
+        let buffer = SharedBuffer::new([0u8; 4]);
+        let a = buffer.share();
+        let b = buffer.share();
+
+        // Nothing is kept before the first lock
+        let mut guard = a.lock();
+        assert!(!guard.kept());
+        guard.fill(1);
+        drop(guard);
+        let guard = a.lock();
+        assert!(guard.kept());
+        assert_eq!(*guard, [1; 4]);
+        drop(guard);
+
+        let mut guard = b.lock();
+        assert!(!guard.kept());
+        guard.fill(2);
+        drop(guard);
+        assert!(!a.lock().kept());
+        assert!(a.lock().kept());
+    }
+
+    #[test]
+    fn locks_across_threads() {
+        static BUFFER: SharedBuffer<u64> = SharedBuffer::new(0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let share = BUFFER.share();
+                    for _ in 0..1000 {
+                        let mut guard = share.lock();
+                        let value = *guard;
+                        std::thread::yield_now();
+                        *guard = value + 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(*BUFFER.share().lock(), 4000);
+    }
+}
@@ -0,0 +1,138 @@
+//! Time stretch tests using generated sine tracks
+//!
+//! Run with `cargo test --test stretch_test`.
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::PI;
+    use pmp_core::player::stretch::{TimeStretch, MAX_SPEED, MIN_SPEED};
+
+    const RATE: usize = 44100;
+
+    /// Stretch a second of generated stereo sine, returns the frames produced, the measured
+    /// frequency and the peak amplitude
+    fn measure(freq: f32, speed: f32, amplitude: [f32; 2]) -> (usize, f32, [f32; 2]) {
+        let mut stretch = TimeStretch::new();
+        stretch.configure(2);
+        stretch.set_speed(speed);
+
+        let mut input = [0f32; 1152 * 2];
+        let mut output = [0f32; 1152 * 2];
+        let mut frame = 0usize;
+        let mut crossings = 0usize;
+        let mut produced_frames = 0usize;
+        let mut previous = 0f32;
+        let mut peak = [0f32; 2];
+
+        while frame < RATE {
+            for chunk in input.chunks_exact_mut(2) {
+                let value = libm::sinf(2. * PI * freq * frame as f32 / RATE as f32);
+                chunk[0] = value * amplitude[0];
+                chunk[1] = value * amplitude[1];
+                frame += 1;
+            }
+
+            let mut remaining = &input[..];
+            loop {
+                let (consumed, produced) = stretch.process(remaining, &mut output);
+                if consumed == 0 && produced == 0 {
+                    break;
+                }
+                remaining = &remaining[consumed..];
+                for chunk in output[..produced].chunks_exact(2) {
+                    // Skip the fade in of the first segment
+                    if produced_frames > 640 {
+                        if previous < 0. && chunk[0] >= 0. {
+                            crossings += 1;
+                        }
+                        peak[0] = peak[0].max(chunk[0].abs());
+                        peak[1] = peak[1].max(chunk[1].abs());
+                    }
+                    previous = chunk[0];
+                    produced_frames += 1;
+                }
+            }
+        }
+
+        let seconds = (produced_frames - 641) as f32 / RATE as f32;
+        (produced_frames, crossings as f32 / seconds, peak)
+    }
+
+    fn assert_stretch(speed: f32) {
+        let (frames, measured, peak) = measure(440., speed, [1., 1.]);
+        let expected = RATE as f32 / speed;
+        assert!(
+            (frames as f32 - expected).abs() < expected * 0.02,
+            "{speed}x: expected {expected} frames, produced {frames}"
+        );
+        assert!(
+            (measured - 440.).abs() < 440. * 0.01,
+            "{speed}x: expected 440 Hz, measured {measured} Hz"
+        );
+        assert!((peak[0] - 1.).abs() < 0.05, "{speed}x: peak {}", peak[0]);
+    }
+
+    #[test]
+    fn passes_through_at_normal_speed() {
+        let mut stretch = TimeStretch::new();
+        stretch.configure(2);
+        assert!(stretch.is_passthrough());
+
+        let input = [0.25f32, -0.5, 0.75, -1.];
+        let mut output = [0f32; 4];
+        assert_eq!(stretch.process(&input, &mut output), (4, 4));
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn keeps_pitch_faster() {
+        assert_stretch(2.);
+        assert_stretch(1.5);
+    }
+
+    #[test]
+    fn keeps_pitch_slower() {
+        assert_stretch(0.75);
+    }
+
+    #[test]
+    fn keeps_silent_channel_silent() {
+        let (_, _, peak) = measure(440., 1.25, [0.5, 0.]);
+        assert!((peak[0] - 0.5).abs() < 0.025, "peak {}", peak[0]);
+        assert_eq!(peak[1], 0.);
+    }
+
+    #[test]
+    fn reports_buffered_input() {
+        let mut stretch = TimeStretch::new();
+        stretch.configure(2);
+        stretch.set_speed(1.5);
+        let mut output = [0f32; 2 * RATE];
+
+        // Less than a window, nothing comes out yet
+        let input = [0.5f32; 2 * 100];
+        assert_eq!(stretch.process(&input, &mut output), (input.len(), 0));
+        assert_eq!(stretch.buffered(), 100);
+
+        let input = [0.5f32; 2 * RATE];
+        let (consumed, _) = stretch.process(&input, &mut output);
+        assert_eq!(consumed, input.len());
+        assert!(stretch.buffered() < 1536, "buffered {}", stretch.buffered());
+
+        stretch.reset();
+        assert_eq!(stretch.buffered(), 0);
+        stretch.set_speed(1.);
+        assert_eq!(stretch.buffered(), 0);
+    }
+
+    #[test]
+    fn clamps_speed() {
+        let mut stretch = TimeStretch::new();
+        stretch.set_speed(4.);
+        assert_eq!(stretch.speed(), MAX_SPEED);
+        stretch.set_speed(0.1);
+        assert_eq!(stretch.speed(), MIN_SPEED);
+        stretch.set_speed(1.);
+        assert!(stretch.is_passthrough());
+    }
+}
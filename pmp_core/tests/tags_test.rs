@@ -0,0 +1,118 @@
+//! Tag reader tests against fixture files
+//!
+//! Run with `cargo test --test tags_test`.
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use pmp_core::{
+        fs::SliceSource,
+        player::tags::{self, id3v2_len},
+    };
+
+    const ID3V23_APE: &[u8] = include_bytes!("fixtures/id3v23_ape.mp3");
+    const ID3V24: &[u8] = include_bytes!("fixtures/id3v24.mp3");
+
+    #[test]
+    fn reads_id3v23_ape_and_id3v1() {
+        let (tags, audio) = tags::read(&mut SliceSource::new(ID3V23_APE)).unwrap();
+
+        // Artwork and all other tags are outside of the audio
+        assert_eq!(audio, 8552..10220);
+
+        // ID3v2 takes precedence over APE and ID3v1
+        assert_eq!(tags.title, "Café Song");
+        assert_eq!(tags.artist, "Ärtist ♪");
+        assert_eq!(tags.album, "Live Album");
+        assert_eq!(tags.track_number, Some(3));
+
+        assert_eq!(tags.replay_gain.track_gain, Some(-6.2));
+        assert_eq!(tags.replay_gain.track_peak, Some(0.988547));
+        assert_eq!(tags.replay_gain.album_gain, Some(1.5));
+        assert_eq!(tags.replay_gain.album_peak, Some(1.02));
+
+        assert_eq!(tags.chapters.len(), 2);
+        assert_eq!(tags.chapters[0].start, Duration::ZERO);
+        assert_eq!(tags.chapters[0].title, "Intro");
+        assert_eq!(tags.chapters[1].start, Duration::from_secs(60));
+        assert_eq!(tags.chapters[1].title, "Main Set");
+    }
+
+    #[test]
+    fn reads_id3v24() {
+        let (tags, audio) = tags::read(&mut SliceSource::new(ID3V24)).unwrap();
+        assert_eq!(audio, 113..1781);
+
+        assert_eq!(tags.title, "Tëst Tïtle");
+        // Unsynchronised frame
+        assert_eq!(tags.album, "Alb\u{FF}\u{E0}m");
+        assert_eq!(tags.replay_gain.track_gain, Some(2.5));
+        assert_eq!(tags.replay_gain.album_gain, None);
+
+        // Missing fields filled in from ID3v1.1
+        assert_eq!(tags.artist, "Fallback Artist");
+        assert_eq!(tags.track_number, Some(9));
+    }
+
+    #[test]
+    fn untagged_stream() {
+        let audio = [0xFFu8, 0xFB, 0x90, 0x44, 0, 0, 0, 0];
+        let (tags, range) = tags::read(&mut SliceSource::new(&audio)).unwrap();
+        assert_eq!(range, 0..8);
+        assert!(tags.title.is_empty());
+        assert_eq!(tags.track_number, None);
+    }
+
+    #[test]
+    fn stops_at_corrupt_sizes() {
+        let audio = [0xFFu8, 0xFB, 0x90, 0x44, 0, 0, 0, 0];
+
+        // ID3v2.3 frame claiming almost 4 GiB, after a title frame that is read
+        let mut file = b"ID3\x03\x00\x00\x00\x00\x00\x25".to_vec();
+        file.extend(b"TIT2\x00\x00\x00\x03\x00\x00\x00Hi");
+        file.extend(b"TALB\xFF\xFF\xFF\xF8\x00\x00\x00Album");
+        file.resize(10 + 0x25, 0);
+        file.extend(audio);
+        let (tags, range) = tags::read(&mut SliceSource::new(&file)).unwrap();
+        assert_eq!(tags.title, "Hi");
+        assert!(tags.album.is_empty());
+        assert_eq!(range, 47..55);
+
+        // ID3v2.3 extended header claiming almost 4 GiB
+        let mut file = b"ID3\x03\x00\x40\x00\x00\x00\x25".to_vec();
+        file.extend(b"\xFF\xFF\xFF\xF0");
+        file.resize(10 + 0x25, 0);
+        file.extend(audio);
+        let (tags, range) = tags::read(&mut SliceSource::new(&file)).unwrap();
+        assert!(tags.title.is_empty());
+        assert_eq!(range, 47..55);
+
+        // APEv2 item claiming almost 4 GiB
+        let mut file = audio.to_vec();
+        file.extend(b"\xF0\xFF\xFF\xFF\x00\x00\x00\x00Title\x00Song");
+        let size = 32 + 8 + 6 + 4;
+        file.extend(b"APETAGEX\xD0\x07\x00\x00");
+        file.extend(u32::to_le_bytes(size));
+        file.extend(b"\x01\x00\x00\x00\x00\x00\x00\x00");
+        file.extend([0; 8]);
+        let (tags, range) = tags::read(&mut SliceSource::new(&file)).unwrap();
+        assert!(tags.title.is_empty());
+        assert_eq!(range, 0..8);
+
+        // APEv2 footer claiming a tag of almost 4 GiB with a header
+        let mut file = audio.to_vec();
+        file.extend(b"APETAGEX\xD0\x07\x00\x00\xF0\xFF\xFF\xFF");
+        file.extend(b"\x00\x00\x00\x00\x00\x00\x00\x80");
+        file.extend([0; 8]);
+        let (_, range) = tags::read(&mut SliceSource::new(&file)).unwrap();
+        assert_eq!(range, 0..file.len() as u32);
+    }
+
+    #[test]
+    fn measures_id3v2_tag() {
+        let header = *b"ID3\x04\x00\x00\x00\x00\x02\x01";
+        assert_eq!(id3v2_len(&header), Some(10 + 257));
+        assert_eq!(id3v2_len(&[0xFF, 0xFB, 0x90, 0x44]), None);
+    }
+}
@@ -0,0 +1,110 @@
+//! Volume steps and gain ramp tests
+//!
+//! Run with `cargo test --test volume_test`.
+
+#[cfg(test)]
+mod tests {
+    use pmp_core::player::{
+        gain::db_to_linear,
+        volume::{Ramp, Volume, MAX_DB, MIN_DB, RAMP_MS, STEP_DB},
+    };
+
+    const SAMPLE_RATE: u32 = 44100;
+    const RAMP_FRAMES: usize = (SAMPLE_RATE * RAMP_MS / 1000) as usize;
+
+    /// Run `ramp` for `frames`, checking no two consecutive gains are further apart than
+    /// `max_step`, returns the last gain
+    fn assert_continuous(ramp: &mut Ramp, frames: usize, max_step: f32) -> f32 {
+        let mut last = ramp.gain();
+        for frame in 0..frames {
+            let gain = ramp.advance();
+            assert!(
+                (gain - last).abs() <= max_step + 1e-6,
+                "jump from {last} to {gain} at frame {frame}"
+            );
+            last = gain;
+        }
+        last
+    }
+
+    #[test]
+    fn steps_in_db() {
+        let mut volume = Volume::default();
+        let start = volume.db();
+        volume.step(2);
+        assert_eq!(volume.db(), start + 2. * STEP_DB);
+        volume.step(-1);
+        assert_eq!(volume.db(), start + STEP_DB);
+        assert!((volume.gain() - db_to_linear(start + STEP_DB)).abs() < 1e-6);
+
+        volume.step(100);
+        assert_eq!(volume.db(), MAX_DB);
+        assert_eq!(volume.gain(), 1.);
+
+        // The bottom step is silence
+        volume.step(-1000);
+        assert_eq!(volume.db(), MIN_DB);
+        assert_eq!(volume.gain(), 0.);
+    }
+
+    #[test]
+    fn mute_keeps_level() {
+        let mut volume = Volume::new(-20.);
+        volume.set_muted(true);
+        assert!(volume.is_muted());
+        assert_eq!(volume.gain(), 0.);
+
+        volume.step(1);
+        volume.set_muted(false);
+        assert_eq!(volume.db(), -20. + STEP_DB);
+        assert!((volume.gain() - db_to_linear(-20. + STEP_DB)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ramps_to_target() {
+        let mut ramp = Ramp::new(1., SAMPLE_RATE);
+        assert_eq!(ramp.advance(), 1.);
+
+        ramp.set_target(0.);
+        assert_eq!(ramp.remaining(), RAMP_FRAMES);
+        let last = assert_continuous(&mut ramp, RAMP_FRAMES, 1. / RAMP_FRAMES as f32);
+        assert_eq!(last, 0.);
+        assert_eq!(ramp.remaining(), 0);
+        assert_eq!(ramp.advance(), 0.);
+    }
+
+    #[test]
+    fn retargets_mid_ramp_without_jumping() {
+        let mut ramp = Ramp::new(0., SAMPLE_RATE);
+        ramp.set_target(1.);
+        let max_step = 1. / RAMP_FRAMES as f32;
+        let middle = assert_continuous(&mut ramp, RAMP_FRAMES / 2, max_step);
+        assert!(middle > 0.4 && middle < 0.6);
+
+        // Reversing starts from where the ramp got to
+        ramp.set_target(0.25);
+        assert_eq!(ramp.gain(), middle);
+        let last = assert_continuous(&mut ramp, RAMP_FRAMES, max_step);
+        assert_eq!(last, 0.25);
+    }
+
+    #[test]
+    fn same_target_keeps_ramping() {
+        let mut ramp = Ramp::new(0., SAMPLE_RATE);
+        ramp.set_target(1.);
+        ramp.advance();
+        ramp.set_target(1.);
+        assert_eq!(ramp.remaining(), RAMP_FRAMES - 1);
+        assert_eq!(ramp.target(), 1.);
+    }
+
+    #[test]
+    fn fades_over_given_time() {
+        let mut fade = Ramp::with_ms(1., SAMPLE_RATE, 50);
+        let frames = SAMPLE_RATE as usize / 20;
+        fade.set_target(0.);
+        assert_eq!(fade.remaining(), frames);
+        let last = assert_continuous(&mut fade, frames, 1. / frames as f32);
+        assert_eq!(last, 0.);
+    }
+}
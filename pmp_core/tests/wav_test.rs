@@ -0,0 +1,158 @@
+//! Format detection and WAV decoder tests
+//!
+//! Run with `cargo test --test wav_test`.
+
+#[cfg(test)]
+mod tests {
+    use pmp_core::{
+        codec::{AudioDecoder, Codec, Error, Format, MAX_SAMPLES},
+        fs::SliceSource,
+    };
+
+    /// Build a WAV file of `frames` ramp samples, with a `LIST` chunk ahead of the pcm
+    fn wav(buf: &mut [u8], channels: u16, bits: u16, frames: u32, extensible: bool) -> usize {
+        let width = u32::from(bits / 8);
+        let block_align = u32::from(channels) * width;
+        let fmt_len: u32 = if extensible { 40 } else { 16 };
+        let data_len = frames * block_align;
+
+        let mut pos = 0;
+        let mut put = |bytes: &[u8]| {
+            buf[pos..pos + bytes.len()].copy_from_slice(bytes);
+            pos += bytes.len();
+        };
+        put(b"RIFF");
+        put(&(4 + 8 + fmt_len + 8 + 4 + 8 + data_len).to_le_bytes());
+        put(b"WAVE");
+
+        put(b"fmt ");
+        put(&fmt_len.to_le_bytes());
+        put(&(if extensible { 0xFFFEu16 } else { 1 }).to_le_bytes());
+        put(&channels.to_le_bytes());
+        put(&48000u32.to_le_bytes());
+        put(&(48000 * block_align).to_le_bytes());
+        put(&(block_align as u16).to_le_bytes());
+        put(&bits.to_le_bytes());
+        if extensible {
+            put(&22u16.to_le_bytes());
+            put(&bits.to_le_bytes());
+            put(&3u32.to_le_bytes());
+            // KSDATAFORMAT_SUBTYPE_PCM
+            put(&[
+                1, 0, 0, 0, 0, 0, 0x10, 0, 0x80, 0, 0, 0xAA, 0, 0x38, 0x9B, 0x71,
+            ]);
+        }
+
+        // Odd length chunk followed by a pad byte
+        put(b"LIST");
+        put(&3u32.to_le_bytes());
+        put(&[1, 2, 3, 0]);
+
+        put(b"data");
+        put(&data_len.to_le_bytes());
+        for frame in 0..frames {
+            for channel in 0..u32::from(channels) {
+                let sample = (frame as i32 * 16 - channel as i32 * 8) << (bits - 16);
+                put(&sample.to_le_bytes()[..width as usize]);
+            }
+        }
+        pos
+    }
+
+    fn decode_all(codec: &mut Codec, source: &mut SliceSource, out: &mut [f32]) -> usize {
+        let mut pcm = [0f32; MAX_SAMPLES];
+        let mut len = 0;
+        while let Some(info) = codec.decode(source, &mut pcm).unwrap() {
+            assert!(info.frames <= 576);
+            let samples = info.frames * info.channels;
+            out[len..len + samples].copy_from_slice(&pcm[..samples]);
+            len += samples;
+        }
+        len
+    }
+
+    #[test]
+    fn detects_format() {
+        assert_eq!(Format::detect(b"RIFF\x24\0\0\0WAVEfmt "), Some(Format::Wav));
+        assert_eq!(Format::detect(&[0xFF, 0xFB, 0x90, 0x44]), Some(Format::Mp3));
+        assert_eq!(Format::detect(b"RIFF\x24\0\0\0AVI "), None);
+        assert_eq!(Format::detect(b"\0\0\0\x20ftypM4A "), None);
+    }
+
+    #[test]
+    fn decodes_16_bit_stereo() {
+        let mut buf = [0u8; 8192];
+        let len = wav(&mut buf, 2, 16, 1000, false);
+        let mut source = SliceSource::new(&buf[..len]);
+
+        let mut codec = Codec::open(&mut source, 0..len as u32).unwrap();
+        assert_eq!(codec.format(), Format::Wav);
+        assert_eq!(codec.sample_rate(), 48000);
+        assert_eq!(codec.channels(), 2);
+        assert_eq!(codec.length(), Some(1000));
+
+        let mut out = [0f32; 2000];
+        assert_eq!(decode_all(&mut codec, &mut source, &mut out), 2000);
+        assert_eq!(out[0], 0.);
+        assert_eq!(out[1], -8. / 32768.);
+        assert_eq!(out[2 * 999], 999. * 16. / 32768.);
+    }
+
+    #[test]
+    fn decodes_24_bit_extensible_mono() {
+        let mut buf = [0u8; 8192];
+        let len = wav(&mut buf, 1, 24, 500, true);
+        let mut source = SliceSource::new(&buf[..len]);
+
+        let mut codec = Codec::open(&mut source, 0..len as u32).unwrap();
+        assert_eq!(codec.channels(), 1);
+        assert_eq!(codec.length(), Some(500));
+
+        let mut out = [0f32; 500];
+        assert_eq!(decode_all(&mut codec, &mut source, &mut out), 500);
+        assert_eq!(out[499], (499 * 16 * 256) as f32 / 8388608.);
+    }
+
+    #[test]
+    fn seeks_exactly() {
+        let mut buf = [0u8; 8192];
+        let len = wav(&mut buf, 2, 16, 1000, false);
+        let mut source = SliceSource::new(&buf[..len]);
+        let mut codec = Codec::open(&mut source, 0..len as u32).unwrap();
+
+        assert_eq!(codec.seek(&mut source, 600), Ok(600));
+        let mut pcm = [0f32; MAX_SAMPLES];
+        let info = codec.decode(&mut source, &mut pcm).unwrap().unwrap();
+        assert_eq!(info.frames, 400);
+        assert_eq!(pcm[0], 600. * 16. / 32768.);
+
+        // Past the end
+        assert_eq!(codec.seek(&mut source, 5000), Ok(1000));
+        assert_eq!(codec.decode(&mut source, &mut pcm), Ok(None));
+    }
+
+    #[test]
+    fn stops_at_audio_end() {
+        let mut buf = [0u8; 8192];
+        let len = wav(&mut buf, 2, 16, 1000, false);
+        let mut source = SliceSource::new(&buf[..len]);
+
+        // Trailing tag over the last 100 frames
+        let end = len as u32 - 400;
+        let codec = Codec::open(&mut source, 0..end).unwrap();
+        assert_eq!(codec.length(), Some(900));
+    }
+
+    #[test]
+    fn rejects_unsupported_pcm() {
+        let mut buf = [0u8; 8192];
+        let len = wav(&mut buf, 2, 16, 10, false);
+        // 8-bit
+        buf[34] = 8;
+        let mut source = SliceSource::new(&buf[..len]);
+        assert!(matches!(
+            Codec::open(&mut source, 0..len as u32),
+            Err(Error::Unsupported)
+        ));
+    }
+}